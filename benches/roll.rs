@@ -0,0 +1,64 @@
+#![feature(test)]
+
+extern crate test;
+
+use riet::interpreter::StackBackend;
+
+use num_bigint::BigInt;
+use test::Bencher;
+
+/// The depth of the stack rolled by each benchmark
+const DEPTH: usize = 100_000;
+
+/// A roll by one buries the top value, which rotates the values left by all but one place
+const ROLL_BY_ONE: usize = DEPTH - 1;
+
+/// A stack which only implements the required methods, so rolls use the default rotate_left
+#[derive(Default, Hash)]
+struct PopPushStack(Vec<BigInt>);
+
+impl StackBackend for PopPushStack {
+    fn push(&mut self, v: BigInt) {
+        self.0.push(v)
+    }
+
+    fn pop(&mut self) -> Option<BigInt> {
+        self.0.pop()
+    }
+
+    fn peek(&self) -> Option<&BigInt> {
+        self.0.last()
+    }
+
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    fn values(&self) -> std::borrow::Cow<'_, [BigInt]> {
+        std::borrow::Cow::Borrowed(&self.0)
+    }
+}
+
+fn deep_stack<S: StackBackend>() -> S {
+    let mut stack = S::default();
+
+    for v in 0..DEPTH {
+        stack.push(BigInt::from(v));
+    }
+
+    stack
+}
+
+#[bench]
+fn roll_100k_deep_vec(b: &mut Bencher) {
+    let mut stack: Vec<BigInt> = deep_stack();
+
+    b.iter(|| stack.rotate_left(DEPTH, ROLL_BY_ONE));
+}
+
+#[bench]
+fn roll_100k_deep_pop_push(b: &mut Bencher) {
+    let mut stack: PopPushStack = deep_stack();
+
+    b.iter(|| stack.rotate_left(DEPTH, ROLL_BY_ONE));
+}
@@ -0,0 +1,130 @@
+use std::fs::File;
+use std::io::{BufReader, Read, Seek};
+use std::path::Path;
+
+use zip::result::ZipError;
+use zip::ZipArchive;
+
+use crate::RietError;
+
+/// The name of the program's image in a bundle
+pub const PROGRAM_ENTRY: &str = "program.png";
+
+/// The name of the program's input in a bundle
+pub const INPUT_ENTRY: &str = "input.txt";
+
+/// A program's image and the input to run it with, loaded from a single zip archive.
+///
+/// This makes it easy to share exactly what's needed to reproduce a run, e.g. in a bug report.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bundle {
+    /// The encoded image of the program
+    pub program: Vec<u8>,
+    /// The input to give the program
+    pub input: Vec<u8>,
+}
+
+impl Bundle {
+    /// Load a bundle from the zip archive at `path`
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, RietError> {
+        Self::from_reader(BufReader::new(File::open(path)?))
+    }
+
+    /// Load a bundle from a zip archive, which must contain both a `program.png` and an
+    /// `input.txt` entry
+    pub fn from_reader(reader: impl Read + Seek) -> Result<Self, RietError> {
+        let mut archive = ZipArchive::new(reader).map_err(invalid_bundle)?;
+
+        Ok(Self {
+            program: read_entry(&mut archive, PROGRAM_ENTRY)?,
+            input: read_entry(&mut archive, INPUT_ENTRY)?,
+        })
+    }
+}
+
+/// Read the whole of the entry called `name`
+fn read_entry(
+    archive: &mut ZipArchive<impl Read + Seek>,
+    name: &str,
+) -> Result<Vec<u8>, RietError> {
+    let mut entry = archive.by_name(name).map_err(|e| match e {
+        ZipError::FileNotFound => RietError::InvalidBundle(format!("missing {}", name)),
+        e => invalid_bundle(e),
+    })?;
+
+    let mut contents = Vec::new();
+    entry.read_to_end(&mut contents)?;
+
+    Ok(contents)
+}
+
+fn invalid_bundle(e: ZipError) -> RietError {
+    RietError::InvalidBundle(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::{Cursor, Write};
+
+    use zip::write::FileOptions;
+    use zip::ZipWriter;
+
+    /// A zip archive in memory containing `entries` of a name and contents
+    fn archive(entries: &[(&str, &[u8])]) -> Cursor<Vec<u8>> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+
+        for (name, contents) in entries {
+            zip.start_file(*name, FileOptions::default()).unwrap();
+            zip.write_all(contents).unwrap();
+        }
+
+        let mut archive = zip.finish().unwrap();
+        archive.set_position(0);
+        archive
+    }
+
+    fn missing_entry(result: Result<Bundle, RietError>) -> Option<String> {
+        match result {
+            Err(RietError::InvalidBundle(message)) => {
+                message.strip_prefix("missing ").map(String::from)
+            }
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn bundles_contain_the_program_and_its_input() {
+        let bundle = Bundle::from_reader(archive(&[
+            (PROGRAM_ENTRY, b"not really a png"),
+            (INPUT_ENTRY, b"42\n"),
+        ]))
+        .unwrap();
+
+        assert_eq!(bundle.program, b"not really a png");
+        assert_eq!(bundle.input, b"42\n");
+    }
+
+    #[test]
+    fn bundles_missing_an_entry_are_invalid() {
+        let without_program = archive(&[(INPUT_ENTRY, b"42\n")]);
+        assert_eq!(
+            missing_entry(Bundle::from_reader(without_program)).as_deref(),
+            Some(PROGRAM_ENTRY)
+        );
+
+        let without_input = archive(&[(PROGRAM_ENTRY, b"not really a png")]);
+        assert_eq!(
+            missing_entry(Bundle::from_reader(without_input)).as_deref(),
+            Some(INPUT_ENTRY)
+        );
+    }
+
+    #[test]
+    fn files_which_are_not_zip_archives_are_invalid() {
+        let result = Bundle::from_reader(Cursor::new(b"program.png and input.txt".to_vec()));
+
+        assert!(matches!(result, Err(RietError::InvalidBundle(_))));
+    }
+}
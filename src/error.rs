@@ -0,0 +1,121 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use image::ImageError;
+
+/// The ways loading or running a program can fail
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum RietError {
+    /// The codel size was zero
+    InvalidCodelSize,
+    /// The program has no codels
+    EmptyProgram,
+    /// A grid of codels didn't have exactly one codel for each row and column
+    GridSize {
+        rows: u32,
+        cols: u32,
+        found: usize,
+    },
+    /// The image couldn't be decoded or saved
+    Image(ImageError),
+    Io(io::Error),
+    /// A PNG was truncated or the codel size stored in its metadata was invalid
+    InvalidMetadata(String),
+    /// A character in a program written as text doesn't stand for any colour
+    UnknownAsciiColor {
+        row: u32,
+        col: u32,
+        c: char,
+    },
+    /// A serialized program was invalid or from an unsupported version
+    InvalidSerializedProgram(String),
+    /// A bundle couldn't be read or was missing one of its entries
+    InvalidBundle(String),
+    /// An operation table didn't have exactly one operation for each hue and lightness change
+    InvalidOperationTable(String),
+    UnknownOperation(String),
+    UnknownCharEncoding(String),
+    UnknownSampler(String),
+    UnknownEmptyOut(String),
+    /// The pointer was inside a black block, which it can never leave
+    InsideBlack,
+    /// The pointer was on a codel which isn't part of the program, so has no colour block
+    NoColorBlock {
+        row: u32,
+        col: u32,
+    },
+}
+
+impl fmt::Display for RietError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RietError::InvalidCodelSize => write!(f, "The codel size must be at least 1"),
+            RietError::EmptyProgram => write!(f, "The program image has no codels"),
+            RietError::GridSize { rows, cols, found } => write!(
+                f,
+                "A {}x{} program needs {} codels, but the grid has {}",
+                rows,
+                cols,
+                *rows as u64 * *cols as u64,
+                found
+            ),
+            RietError::Image(e) => write!(f, "{}", e),
+            RietError::Io(e) => write!(f, "{}", e),
+            RietError::InvalidMetadata(msg) => write!(f, "Invalid PNG metadata: {}", msg),
+            RietError::UnknownAsciiColor { row, col, c } => write!(
+                f,
+                "Unknown colour {:?} at row {}, column {} of the program",
+                c, row, col
+            ),
+            RietError::InvalidSerializedProgram(msg) => {
+                write!(f, "Invalid serialized program: {}", msg)
+            }
+            RietError::InvalidBundle(msg) => write!(f, "Invalid bundle: {}", msg),
+            RietError::InvalidOperationTable(msg) => write!(f, "Invalid operation table: {}", msg),
+            RietError::UnknownOperation(name) => write!(f, "Unknown operation: {:?}", name),
+            RietError::UnknownCharEncoding(name) => write!(
+                f,
+                "Unknown char encoding {:?}, expected utf8, latin1 or raw",
+                name
+            ),
+            RietError::UnknownSampler(name) => {
+                write!(f, "Unknown sampler {:?}, expected majority or center", name)
+            }
+            RietError::UnknownEmptyOut(name) => write!(
+                f,
+                "Unknown empty out mode {:?}, expected ignore, error or placeholder",
+                name
+            ),
+            RietError::InsideBlack => write!(f, "Cannot execute from a inside black block"),
+            RietError::NoColorBlock { row, col } => write!(
+                f,
+                "The codel at row {} column {} isn't part of any colour block",
+                row, col
+            ),
+        }
+    }
+}
+
+impl Error for RietError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RietError::Image(e) => Some(e),
+            RietError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<ImageError> for RietError {
+    fn from(e: ImageError) -> Self {
+        RietError::Image(e)
+    }
+}
+
+impl From<io::Error> for RietError {
+    fn from(e: io::Error) -> Self {
+        RietError::Io(e)
+    }
+}
@@ -0,0 +1,397 @@
+use super::{
+    CharEncoding, Clock, EmptyOut, Interpreter, Operation, OperationTable, OutputCallback,
+    OutputEvent, PietState, StackBackend, SystemClock, TraceCallback, TraceEvent,
+    DEFAULT_TRACE_STACK_LIMIT, OPERATION_TABLE,
+};
+use crate::program::Program;
+
+use std::collections::HashSet;
+use std::io::{self, BufRead, BufReader, Write};
+use std::time::Duration;
+
+/// The text written by `out` with an empty stack when [`EmptyOut::Placeholder`] is chosen
+pub const DEFAULT_EMPTY_OUT_PLACEHOLDER: &str = "?";
+
+/// The number of times the pointer tries to leave a block before the program is halted
+pub const DEFAULT_ESCAPE_ATTEMPTS: usize = 8;
+
+/// The number of states the pointer can pass through in one slide across white before the
+/// program is halted, which is far more than any slide which doesn't loop forever needs
+pub const DEFAULT_MAX_WHITE_STATES: usize = 1 << 20;
+
+/// Configures and constructs an [`Interpreter`].
+///
+/// Any setting which isn't given takes the same default as [`Interpreter::new`]: input is read
+/// from stdin, output is written to stdout, and the program runs without any limits.
+pub struct InterpreterBuilder {
+    program: Program,
+    input: Box<dyn BufRead + Send>,
+    prompt: bool,
+    output: Box<dyn Write + Send>,
+    number_output: Option<Box<dyn Write + Send>>,
+    char_output: Option<Box<dyn Write + Send>>,
+    max_number_digits: Option<usize>,
+    char_encoding: CharEncoding,
+    operation_table: OperationTable,
+    max_steps: Option<usize>,
+    max_ops: Option<usize>,
+    max_underflows: Option<usize>,
+    timeout: Option<Duration>,
+    clock: Box<dyn Clock>,
+    time_steps: bool,
+    max_stack: Option<usize>,
+    max_output_bytes: Option<usize>,
+    escape_attempts: usize,
+    cycle_detection: bool,
+    track_provenance: bool,
+    record_stack_depths: bool,
+    alternate_white_restrictions: bool,
+    max_white_states: usize,
+    trace_stack_limit: usize,
+    trace_full_stack: bool,
+    trace_operations: Option<HashSet<Operation>>,
+    trace_callback: Option<TraceCallback>,
+    output_callback: Option<OutputCallback>,
+    empty_out: EmptyOut,
+    empty_out_placeholder: String,
+    #[cfg(feature = "precompile")]
+    precompile_moves: bool,
+    trailing_newline: bool,
+}
+
+impl InterpreterBuilder {
+    pub fn new(program: Program) -> Self {
+        Self {
+            program,
+            input: Box::new(BufReader::new(io::stdin())),
+            prompt: true,
+            output: Box::new(io::stdout()),
+            number_output: None,
+            char_output: None,
+            max_number_digits: None,
+            char_encoding: CharEncoding::default(),
+            operation_table: OPERATION_TABLE,
+            max_steps: None,
+            max_ops: None,
+            max_underflows: None,
+            timeout: None,
+            clock: Box::new(SystemClock::new()),
+            time_steps: false,
+            max_stack: None,
+            max_output_bytes: None,
+            escape_attempts: DEFAULT_ESCAPE_ATTEMPTS,
+            cycle_detection: false,
+            track_provenance: false,
+            record_stack_depths: false,
+            alternate_white_restrictions: false,
+            max_white_states: DEFAULT_MAX_WHITE_STATES,
+            trace_stack_limit: DEFAULT_TRACE_STACK_LIMIT,
+            trace_full_stack: false,
+            trace_operations: None,
+            trace_callback: None,
+            output_callback: None,
+            empty_out: EmptyOut::default(),
+            empty_out_placeholder: DEFAULT_EMPTY_OUT_PLACEHOLDER.to_string(),
+            #[cfg(feature = "precompile")]
+            precompile_moves: false,
+            trailing_newline: false,
+        }
+    }
+
+    /// Read the input for `in(char)` and `in(number)` from `input`.
+    ///
+    /// `in(char)` consumes a single character and `in(number)` consumes a decimal integer after
+    /// skipping whitespace, both from the same buffer, so they can read from one stream in any
+    /// order.
+    pub fn input(mut self, input: impl BufRead + Send + 'static) -> Self {
+        self.input = Box::new(input);
+        self
+    }
+
+    /// Whether to write a "> " prompt to stderr before `in(char)` and `in(number)` read input
+    pub fn prompt(mut self, prompt: bool) -> Self {
+        self.prompt = prompt;
+        self
+    }
+
+    /// Write the output of `out(char)` and `out(number)` to `output`
+    pub fn output(mut self, output: impl Write + Send + 'static) -> Self {
+        self.output = Box::new(output);
+        self
+    }
+
+    /// Write the output of `out(number)` to `output` instead of the shared output
+    pub fn number_output(mut self, output: impl Write + Send + 'static) -> Self {
+        self.number_output = Some(Box::new(output));
+        self
+    }
+
+    /// Write the output of `out(char)` to `output` instead of the shared output
+    pub fn char_output(mut self, output: impl Write + Send + 'static) -> Self {
+        self.char_output = Some(Box::new(output));
+        self
+    }
+
+    /// Skip `out(number)` with a warning when the number has more than `max_digits` digits
+    pub fn max_number_digits(mut self, max_digits: usize) -> Self {
+        self.max_number_digits = Some(max_digits);
+        self
+    }
+
+    /// Encode the output of `out(char)` with `encoding` instead of UTF-8
+    pub fn char_encoding(mut self, encoding: CharEncoding) -> Self {
+        self.char_encoding = encoding;
+        self
+    }
+
+    /// Decode operations with `table` instead of the standard piet table, to run a dialect
+    pub fn operation_table(mut self, table: OperationTable) -> Self {
+        self.operation_table = table;
+        self
+    }
+
+    /// Halt the program once it has taken `max_steps` steps
+    pub fn max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = Some(max_steps);
+        self
+    }
+
+    /// Halt the program once it has executed `max_ops` operations.
+    ///
+    /// Unlike the maximum number of steps, this doesn't count steps which only move the pointer,
+    /// such as sliding out of white.
+    pub fn max_ops(mut self, max_ops: usize) -> Self {
+        self.max_ops = Some(max_ops);
+        self
+    }
+
+    /// Halt the program once operations have failed due to a stack underflow more than
+    /// `max_underflows` times, as a program which underflows often is likely misnavigating
+    pub fn max_underflows(mut self, max_underflows: usize) -> Self {
+        self.max_underflows = Some(max_underflows);
+        self
+    }
+
+    /// Halt the program once it has been running for longer than `timeout`
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Measure the timeout and step timings against `clock` rather than the real monotonic
+    /// clock, e.g. a [`MockClock`](super::MockClock) to test timeouts deterministically
+    pub fn clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Box::new(clock);
+        self
+    }
+
+    /// Time every step to find the slowest, which is shown in the [`Summary`](super::Summary).
+    ///
+    /// This reads the clock twice every step, so is off by default.
+    pub fn time_steps(mut self, time_steps: bool) -> Self {
+        self.time_steps = time_steps;
+        self
+    }
+
+    /// Halt the program if the stack grows beyond `max_stack` values
+    pub fn max_stack(mut self, max_stack: usize) -> Self {
+        self.max_stack = Some(max_stack);
+        self
+    }
+
+    /// Write a newline when the program halts if its output doesn't already end in one
+    pub fn trailing_newline(mut self, trailing_newline: bool) -> Self {
+        self.trailing_newline = trailing_newline;
+        self
+    }
+
+    /// Choose what `out(char)` and `out(number)` do when the stack is empty
+    pub fn empty_out(mut self, empty_out: EmptyOut) -> Self {
+        self.empty_out = empty_out;
+        self
+    }
+
+    /// The text written in place of a value when `out` is executed with an empty stack and
+    /// [`EmptyOut::Placeholder`] is chosen
+    pub fn empty_out_placeholder(mut self, placeholder: impl Into<String>) -> Self {
+        self.empty_out_placeholder = placeholder.into();
+        self
+    }
+
+    /// Halt the program once `out(char)` and `out(number)` would write more than `max_bytes` bytes
+    pub fn max_output_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_output_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Set the number of times the pointer tries to leave a block before the program is halted
+    pub fn escape_attempts(mut self, escape_attempts: usize) -> Self {
+        self.escape_attempts = escape_attempts;
+        self
+    }
+
+    /// Halt the program if it ever returns to a state it has already been in.
+    ///
+    /// This remembers a hash of every state the program passes through, which is forgotten
+    /// whenever input is read, as the program may not loop once it receives different input.
+    pub fn cycle_detection(mut self, cycle_detection: bool) -> Self {
+        self.cycle_detection = cycle_detection;
+        self
+    }
+
+    /// Record the step which pushed each value on the stack, see [`Interpreter::provenance`].
+    ///
+    /// This keeps a second stack alongside the program's, so is off by default.
+    pub fn track_provenance(mut self, track_provenance: bool) -> Self {
+        self.track_provenance = track_provenance;
+        self
+    }
+
+    /// Record the depth of the stack after every step, see [`Interpreter::stack_depths`]
+    pub fn record_stack_depths(mut self, record_stack_depths: bool) -> Self {
+        self.record_stack_depths = record_stack_depths;
+        self
+    }
+
+    /// Alternate between toggling the CC and rotating the DP when sliding into a restriction.
+    ///
+    /// By default both are done at every restriction, as the spec describes, but some
+    /// interpreters alternate between them as they do when leaving a coloured block.
+    ///
+    /// The CC doesn't steer a slide, so a slide leaves white with the same DP and CC either way,
+    /// but alternating takes twice as many restrictions and so visits more states on the way,
+    /// which counts towards [`max_white_states`](Self::max_white_states).
+    pub fn alternate_white_restrictions(mut self, alternate: bool) -> Self {
+        self.alternate_white_restrictions = alternate;
+        self
+    }
+
+    /// Halt the program when a slide across white passes through more than `max_states` states.
+    ///
+    /// Every state visited in a slide is remembered to detect the pointer looping forever, so
+    /// this bounds the memory used by a slide across an enormous white block.
+    pub fn max_white_states(mut self, max_states: usize) -> Self {
+        self.max_white_states = max_states;
+        self
+    }
+
+    /// Set the maximum number of stack entries shown when tracing the full stack
+    pub fn trace_stack_limit(mut self, trace_stack_limit: usize) -> Self {
+        self.trace_stack_limit = trace_stack_limit;
+        self
+    }
+
+    /// Trace the whole stack after every step, rather than how each operation changed it
+    pub fn trace_full_stack(mut self, trace_full_stack: bool) -> Self {
+        self.trace_full_stack = trace_full_stack;
+        self
+    }
+
+    /// Only emit the trace output for executing an operation for the operations in `ops`
+    pub fn trace_operations(mut self, ops: impl IntoIterator<Item = Operation>) -> Self {
+        self.trace_operations = Some(ops.into_iter().collect());
+        self
+    }
+
+    /// Work out the move out of every coloured block for each DP and CC when the interpreter is
+    /// built, so steps which go straight from one block into the next skip navigating the
+    /// block's edges and decoding the operation.
+    ///
+    /// Moves which turn the pointer or slide through white are still taken by the general
+    /// interpreter. This is experimental, so is off by default.
+    #[cfg(feature = "precompile")]
+    pub fn precompile_moves(mut self, precompile_moves: bool) -> Self {
+        self.precompile_moves = precompile_moves;
+        self
+    }
+
+    /// Call `callback` after every step of the program, independently of the `log` crate
+    pub fn trace_callback(mut self, callback: impl FnMut(&TraceEvent) + Send + 'static) -> Self {
+        self.trace_callback = Some(Box::new(callback));
+        self
+    }
+
+    /// Call `callback` with every value the program writes, as it's written.
+    ///
+    /// The values are still written to the outputs, which can be set to [`io::sink`] if they
+    /// are only needed by the callback.
+    pub fn output_callback(mut self, callback: impl FnMut(OutputEvent) + Send + 'static) -> Self {
+        self.output_callback = Some(Box::new(callback));
+        self
+    }
+
+    pub fn build(self) -> Interpreter {
+        self.build_with_stack(Vec::new())
+    }
+
+    /// Build an interpreter which keeps its stack in `stack` rather than a `Vec`
+    pub fn build_with_stack<S: StackBackend>(self, stack: S) -> Interpreter<S> {
+        // values already on the stack are attributed to the first step
+        let provenance = self.track_provenance.then(|| vec![0; stack.len()]);
+        let max_stack_depth = stack.len();
+
+        #[cfg(feature = "precompile")]
+        let precompile_moves = self.precompile_moves;
+
+        let interpreter = Interpreter {
+            program: self.program,
+            state: PietState {
+                stack,
+                ..Default::default()
+            },
+            step_no: 0,
+            escapes: 0,
+            white_entries: 0,
+            slid_across_white: false,
+            trace_stack_limit: self.trace_stack_limit,
+            trace_full_stack: self.trace_full_stack,
+            trace_operations: self.trace_operations,
+            input: self.input,
+            prompt: self.prompt,
+            output: self.output,
+            number_output: self.number_output,
+            char_output: self.char_output,
+            max_number_digits: self.max_number_digits,
+            char_encoding: self.char_encoding,
+            operation_table: self.operation_table,
+            max_steps: self.max_steps,
+            max_ops: self.max_ops,
+            ops_executed: 0,
+            max_underflows: self.max_underflows,
+            underflows: 0,
+            timeout: self.timeout,
+            max_stack: self.max_stack,
+            max_output_bytes: self.max_output_bytes,
+            output_bytes: 0,
+            escape_attempts: self.escape_attempts,
+            cycle_detection: self.cycle_detection,
+            alternate_white_restrictions: self.alternate_white_restrictions,
+            max_white_states: self.max_white_states,
+            seen_states: Default::default(),
+            provenance,
+            stack_depths: self.record_stack_depths.then(Vec::new),
+            max_stack_depth,
+            initial_stack: Vec::new(),
+            clock: self.clock,
+            started: None,
+            elapsed: Duration::ZERO,
+            time_steps: self.time_steps,
+            slowest_step: None,
+            trace_callback: self.trace_callback,
+            output_callback: self.output_callback,
+            last_output: None,
+            empty_out: self.empty_out,
+            empty_out_placeholder: self.empty_out_placeholder,
+            empty_out_halted: false,
+            #[cfg(feature = "precompile")]
+            precompiled: None,
+            trailing_newline: self.trailing_newline,
+            unterminated_output: None,
+        };
+
+        #[cfg(feature = "precompile")]
+        let interpreter = interpreter.precompile_moves(precompile_moves);
+
+        interpreter
+    }
+}
@@ -0,0 +1,154 @@
+use std::time::{Duration, Instant};
+
+#[cfg(any(test, feature = "testing"))]
+use std::sync::{Arc, Mutex};
+
+/// The source of time the interpreter measures timeouts and step timings against.
+///
+/// Only the differences between readings are used, so `now` can count from any fixed point.
+pub trait Clock: Send {
+    /// The time since the clock's starting point
+    fn now(&self) -> Duration;
+}
+
+/// The real monotonic clock, which the interpreter uses by default
+#[derive(Debug, Copy, Clone)]
+pub struct SystemClock {
+    origin: Instant,
+}
+
+impl SystemClock {
+    pub fn new() -> Self {
+        Self {
+            origin: Instant::now(),
+        }
+    }
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.origin.elapsed()
+    }
+}
+
+/// A clock which only moves when it is advanced, so timeouts can be tested deterministically.
+///
+/// Clones share the same time, so a clone can be given to the interpreter and the original
+/// advanced from the test.
+#[cfg(any(test, feature = "testing"))]
+#[derive(Debug, Default, Clone)]
+pub struct MockClock {
+    now: Arc<Mutex<Duration>>,
+}
+
+#[cfg(any(test, feature = "testing"))]
+impl MockClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Move the clock forward by `by`
+    pub fn advance(&self, by: Duration) {
+        *self.now.lock().unwrap() += by;
+    }
+}
+
+#[cfg(any(test, feature = "testing"))]
+impl Clock for MockClock {
+    fn now(&self) -> Duration {
+        *self.now.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::RunConfig;
+    use crate::{HaltReason, Interpreter, Program, StepOutcome};
+
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    /// A program which moves back and forth between two blocks forever
+    fn endless_program() -> Program {
+        Program::from_ascii("RG\n").unwrap()
+    }
+
+    /// A clock which moves forward a millisecond every time it is read
+    #[derive(Clone, Default)]
+    struct TickingClock {
+        reads: Arc<AtomicU64>,
+    }
+
+    impl TickingClock {
+        fn reads(&self) -> u64 {
+            self.reads.load(Ordering::Relaxed)
+        }
+    }
+
+    impl Clock for TickingClock {
+        fn now(&self) -> Duration {
+            Duration::from_millis(self.reads.fetch_add(1, Ordering::Relaxed))
+        }
+    }
+
+    #[test]
+    fn timeout_halts_once_the_mock_clock_passes_it() {
+        let clock = MockClock::new();
+        let mut interpreter = Interpreter::builder(endless_program())
+            .clock(clock.clone())
+            .timeout(Duration::from_secs(10))
+            .build();
+
+        for _ in 0..100 {
+            assert!(matches!(interpreter.step(), Ok(StepOutcome::Moved { .. })));
+        }
+
+        clock.advance(Duration::from_secs(10));
+        assert!(matches!(interpreter.step(), Ok(StepOutcome::Moved { .. })));
+
+        clock.advance(Duration::from_millis(1));
+        assert!(matches!(
+            interpreter.step(),
+            Ok(StepOutcome::Halted(HaltReason::Timeout))
+        ));
+        assert_eq!(interpreter.summary().steps, 101);
+    }
+
+    #[test]
+    fn run_with_timeout_is_measured_against_the_clock() {
+        let clock = TickingClock::default();
+        let mut interpreter = Interpreter::builder(endless_program())
+            .clock(clock.clone())
+            .build();
+
+        let config = RunConfig {
+            timeout: Some(Duration::from_millis(50)),
+            ..Default::default()
+        };
+
+        assert_eq!(interpreter.run_with(config).unwrap(), HaltReason::Timeout);
+        assert!(interpreter.summary().steps < 50);
+    }
+
+    #[test]
+    fn clock_is_only_sampled_without_a_timeout() {
+        let clock = TickingClock::default();
+        let mut interpreter = Interpreter::builder(endless_program())
+            .clock(clock.clone())
+            .max_steps(10_000)
+            .build();
+
+        assert_eq!(interpreter.run().unwrap(), HaltReason::MaxSteps);
+        assert!(
+            clock.reads() < 100,
+            "the clock was read {} times",
+            clock.reads()
+        );
+    }
+}
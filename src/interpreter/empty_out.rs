@@ -0,0 +1,42 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::RietError;
+
+/// What `out(char)` and `out(number)` do when the stack is empty
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum EmptyOut {
+    /// Nothing is written, as with every other operation which underflows the stack
+    #[default]
+    Ignore,
+    /// The program is halted before its next step
+    Error,
+    /// A placeholder is written in place of the missing value
+    Placeholder,
+}
+
+impl FromStr for EmptyOut {
+    type Err = RietError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ignore" => Ok(EmptyOut::Ignore),
+            "error" => Ok(EmptyOut::Error),
+            "placeholder" => Ok(EmptyOut::Placeholder),
+            _ => Err(RietError::UnknownEmptyOut(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for EmptyOut {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        #[rustfmt::skip]
+        match self {
+            EmptyOut::Ignore      => write!(f, "ignore")?,
+            EmptyOut::Error       => write!(f, "error")?,
+            EmptyOut::Placeholder => write!(f, "placeholder")?,
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,71 @@
+use std::fmt;
+use std::str::FromStr;
+
+use num_bigint::BigInt;
+use num_traits::cast::ToPrimitive;
+
+use crate::RietError;
+
+/// How `out(char)` encodes the value it pops off the stack
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum CharEncoding {
+    /// The value is a unicode code point written as UTF-8, other values aren't written
+    #[default]
+    Utf8,
+    /// The value is written as a single Latin-1 byte, values outside 0-255 aren't written
+    Latin1,
+    /// The lowest byte of the value is written, so every value is written
+    Raw,
+}
+
+impl CharEncoding {
+    /// Encode `n`, or `None` if it can't be represented in this encoding
+    pub fn encode(self, n: &BigInt) -> Option<Vec<u8>> {
+        match self {
+            CharEncoding::Utf8 => {
+                let c = n.to_u32().and_then(char::from_u32)?;
+
+                Some(c.to_string().into_bytes())
+            }
+            CharEncoding::Latin1 => n.to_u8().map(|b| vec![b]),
+            // the lowest byte of the two's complement representation, so -1 is 0xFF
+            CharEncoding::Raw => n.to_signed_bytes_le().first().map(|&b| vec![b]),
+        }
+    }
+
+    /// The character `n` is written as, with single bytes read as Latin-1, or `None` if it
+    /// can't be represented in this encoding
+    pub fn to_char(self, n: &BigInt) -> Option<char> {
+        match self {
+            CharEncoding::Utf8 => n.to_u32().and_then(char::from_u32),
+            CharEncoding::Latin1 => n.to_u8().map(char::from),
+            CharEncoding::Raw => n.to_signed_bytes_le().first().map(|&b| char::from(b)),
+        }
+    }
+}
+
+impl FromStr for CharEncoding {
+    type Err = RietError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "utf8" => Ok(CharEncoding::Utf8),
+            "latin1" => Ok(CharEncoding::Latin1),
+            "raw" => Ok(CharEncoding::Raw),
+            _ => Err(RietError::UnknownCharEncoding(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for CharEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        #[rustfmt::skip]
+        match self {
+            CharEncoding::Utf8   => write!(f, "utf8")?,
+            CharEncoding::Latin1 => write!(f, "latin1")?,
+            CharEncoding::Raw    => write!(f, "raw")?,
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,218 @@
+use std::io::{self, BufRead, Read, Write};
+
+use num_bigint::BigInt;
+
+/// An input source which copies every byte the interpreter consumes to a recording.
+///
+/// The recording holds exactly the bytes consumed, so reading it back in as input replays the
+/// session which produced it.
+pub struct RecordingReader<R, W> {
+    inner: R,
+    record: W,
+}
+
+impl<R: BufRead, W: Write> RecordingReader<R, W> {
+    pub fn new(inner: R, record: W) -> Self {
+        Self { inner, record }
+    }
+
+    fn record(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.record.write_all(bytes)?;
+        self.record.flush()
+    }
+}
+
+impl<R: BufRead, W: Write> Read for RecordingReader<R, W> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.record(&buf[..n])?;
+
+        Ok(n)
+    }
+}
+
+impl<R: BufRead, W: Write> BufRead for RecordingReader<R, W> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        // the buffer is already filled so this cannot block or read any more input
+        let consumed = match self.inner.fill_buf() {
+            Ok(buf) => buf[..amt].to_vec(),
+            Err(e) => {
+                log::warn!("Failed to record input: {}", e);
+                vec![]
+            }
+        };
+
+        if let Err(e) = self.record(&consumed) {
+            log::warn!("Failed to record input: {}", e);
+        }
+
+        self.inner.consume(amt);
+    }
+}
+
+/// The next byte of `input`, without consuming it
+fn peek_byte<R: BufRead + ?Sized>(input: &mut R) -> io::Result<Option<u8>> {
+    Ok(input.fill_buf()?.first().copied())
+}
+
+/// Consume a single UTF-8 character from `input`.
+///
+/// Returns `None` at the end of the input, or if the bytes read weren't a valid character.
+pub(crate) fn read_char<R: BufRead + ?Sized>(input: &mut R) -> io::Result<Option<char>> {
+    let first = match peek_byte(input)? {
+        Some(b) => b,
+        None => return Ok(None),
+    };
+
+    let width = match first {
+        0x00..=0x7F => 1,
+        0xC0..=0xDF => 2,
+        0xE0..=0xEF => 3,
+        0xF0..=0xF7 => 4,
+        _ => 1,
+    };
+
+    // a character may be split across two fills of the buffer, so take it a byte at a time
+    let mut bytes = Vec::with_capacity(width);
+    while bytes.len() < width {
+        match peek_byte(input)? {
+            Some(b) => {
+                bytes.push(b);
+                input.consume(1);
+            }
+            None => break,
+        }
+    }
+
+    Ok(std::str::from_utf8(&bytes)
+        .ok()
+        .and_then(|s| s.chars().next()))
+}
+
+/// Consume a decimal integer from `input`, skipping any whitespace before it.
+///
+/// Only the optional sign and digits making up the number are consumed, so whatever follows it
+/// is left for the next read. Returns `None` if there was no number before the next
+/// non-whitespace character or the end of the input, in which case everything up to the next
+/// whitespace is consumed as well, so a lone sign or a word is skipped the same way.
+pub(crate) fn read_number<R: BufRead + ?Sized>(input: &mut R) -> io::Result<Option<BigInt>> {
+    while let Some(b) = peek_byte(input)? {
+        if !b.is_ascii_whitespace() {
+            break;
+        }
+
+        input.consume(1);
+    }
+
+    let mut token = String::new();
+
+    if let Some(sign @ (b'-' | b'+')) = peek_byte(input)? {
+        if sign == b'-' {
+            token.push('-');
+        }
+
+        input.consume(1);
+    }
+
+    let sign_len = token.len();
+
+    while let Some(b) = peek_byte(input)? {
+        if !b.is_ascii_digit() {
+            break;
+        }
+
+        token.push(b as char);
+        input.consume(1);
+    }
+
+    if token.len() == sign_len {
+        while let Some(b) = peek_byte(input)? {
+            if b.is_ascii_whitespace() {
+                break;
+            }
+
+            input.consume(1);
+        }
+
+        return Ok(None);
+    }
+
+    Ok(token.parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{run_and_collect, HaltReason, Interpreter, Program, RietError};
+
+    use std::io::Cursor;
+
+    /// An input which fails every read
+    struct FailingInput;
+
+    impl Read for FailingInput {
+        fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::ConnectionReset.into())
+        }
+    }
+
+    impl BufRead for FailingInput {
+        fn fill_buf(&mut self) -> io::Result<&[u8]> {
+            Err(io::ErrorKind::ConnectionReset.into())
+        }
+
+        fn consume(&mut self, _amt: usize) {}
+    }
+
+    #[test]
+    fn invalid_numbers_are_consumed_up_to_the_next_whitespace() {
+        let mut input = Cursor::new("abc - + -x 12ab\u{e9}c 7");
+
+        for _ in 0..4 {
+            assert_eq!(read_number(&mut input).unwrap(), None);
+        }
+
+        assert_eq!(read_number(&mut input).unwrap(), Some(12.into()));
+        assert_eq!(read_char(&mut input).unwrap(), Some('a'));
+        assert_eq!(read_number(&mut input).unwrap(), None);
+        assert_eq!(read_number(&mut input).unwrap(), Some(7.into()));
+        assert_eq!(read_number(&mut input).unwrap(), None);
+    }
+
+    #[test]
+    fn in_number_and_in_char_can_be_interleaved() {
+        // in(number), in(char), out(char), out(number)
+        let program = Program::from_ascii("Rbc3y#\n").unwrap();
+        let run = |input: &str| {
+            let (output, reason) = run_and_collect(program.clone(), input.as_bytes(), 4).unwrap();
+            assert_eq!(reason, HaltReason::MaxSteps);
+            output
+        };
+
+        assert_eq!(run("-12x"), "x-12");
+        assert_eq!(run(" 34 y"), " 34");
+        // the invalid number is skipped, so in(char) reads what follows it
+        assert_eq!(run("abc!"), "");
+        assert_eq!(run("abc d"), " ");
+        assert_eq!(run("+ d"), " ");
+    }
+
+    #[test]
+    fn failing_to_read_input_is_an_error() {
+        // in(number)
+        let program = Program::from_ascii("Rb\n").unwrap();
+        let mut interpreter = Interpreter::builder(program)
+            .input(FailingInput)
+            .prompt(false)
+            .build();
+
+        assert!(matches!(
+            interpreter.step(),
+            Err(RietError::Io(e)) if e.kind() == io::ErrorKind::ConnectionReset
+        ));
+    }
+}
@@ -0,0 +1,1754 @@
+mod builder;
+pub use builder::{
+    InterpreterBuilder, DEFAULT_EMPTY_OUT_PLACEHOLDER, DEFAULT_ESCAPE_ATTEMPTS,
+    DEFAULT_MAX_WHITE_STATES,
+};
+
+mod clock;
+#[cfg(any(test, feature = "testing"))]
+pub use clock::MockClock;
+pub use clock::{Clock, SystemClock};
+
+mod empty_out;
+pub use empty_out::EmptyOut;
+
+mod encoding;
+pub use encoding::CharEncoding;
+
+mod input;
+pub use input::RecordingReader;
+use input::{read_char, read_number};
+
+mod operation;
+pub use operation::{
+    invert_lightness, parse_operation_table, Operation, OperationTable, Transition, OPERATION_TABLE,
+};
+
+mod outcome;
+pub use outcome::{HaltReason, StepOutcome};
+
+mod output;
+pub use output::{OutputCallback, OutputEvent};
+
+#[cfg(feature = "precompile")]
+mod precompiled;
+
+mod run_config;
+pub use run_config::RunConfig;
+
+mod snapshot;
+pub use snapshot::Snapshot;
+
+mod stack;
+pub use stack::StackBackend;
+
+mod summary;
+pub use summary::Summary;
+
+mod trace;
+pub use trace::{TraceCallback, TraceEvent};
+
+use crate::program::{CodelChooser, Color, DirectionPointer, Point, Program};
+use crate::RietError;
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::convert::TryInto;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::io::{self, prelude::*};
+use std::time::Duration;
+
+#[allow(unused_imports)]
+use log::{debug, error, info, log_enabled, trace, warn, Level};
+use num_bigint::BigInt;
+use num_traits::cast::ToPrimitive;
+use num_traits::{One, Signed, Zero};
+
+#[derive(Debug, Default, Clone, Hash)]
+struct PietState<S> {
+    dp: DirectionPointer,
+    cc: CodelChooser,
+    curr_codel: Point,
+    stack: S,
+}
+
+/// The direction the pointer is heading while it moves between blocks, and how many times it
+/// was turned to get there
+#[derive(Debug, Copy, Clone)]
+struct Heading {
+    dp: DirectionPointer,
+    cc: CodelChooser,
+    escapes: usize,
+    white_entries: usize,
+}
+
+impl Heading {
+    fn new(dp: DirectionPointer, cc: CodelChooser) -> Self {
+        Self {
+            dp,
+            cc,
+            escapes: 0,
+            white_entries: 0,
+        }
+    }
+}
+
+/// Arguments to pointer and switch larger than this many bits are likely a bug in the program
+const LARGE_ARGUMENT_BITS: u64 = 64;
+
+/// The number of steps between each reading of the clock to update the elapsed time, as
+/// reading it every step can take longer than a cheap step
+const ELAPSED_SAMPLE_STEPS: usize = 1024;
+
+/// The number of stack entries shown in trace output unless configured otherwise
+pub const DEFAULT_TRACE_STACK_LIMIT: usize = 32;
+
+/// Interprets a piet program, keeping its stack in `S`
+pub struct Interpreter<S = Vec<BigInt>> {
+    program: Program,
+    state: PietState<S>,
+    step_no: usize,
+    escapes: usize,
+    white_entries: usize,
+    slid_across_white: bool,
+    trace_stack_limit: usize,
+    trace_full_stack: bool,
+    trace_operations: Option<HashSet<Operation>>,
+    input: Box<dyn BufRead + Send>,
+    prompt: bool,
+    output: Box<dyn Write + Send>,
+    number_output: Option<Box<dyn Write + Send>>,
+    char_output: Option<Box<dyn Write + Send>>,
+    max_number_digits: Option<usize>,
+    char_encoding: CharEncoding,
+    operation_table: OperationTable,
+    max_steps: Option<usize>,
+    max_ops: Option<usize>,
+    ops_executed: usize,
+    max_underflows: Option<usize>,
+    underflows: usize,
+    timeout: Option<Duration>,
+    max_stack: Option<usize>,
+    max_output_bytes: Option<usize>,
+    output_bytes: usize,
+    escape_attempts: usize,
+    cycle_detection: bool,
+    alternate_white_restrictions: bool,
+    max_white_states: usize,
+    seen_states: HashSet<u64>,
+    provenance: Option<Vec<usize>>,
+    stack_depths: Option<Vec<usize>>,
+    max_stack_depth: usize,
+    initial_stack: Vec<BigInt>,
+    clock: Box<dyn Clock>,
+    started: Option<Duration>,
+    elapsed: Duration,
+    time_steps: bool,
+    slowest_step: Option<(usize, Duration)>,
+    trace_callback: Option<TraceCallback>,
+    output_callback: Option<OutputCallback>,
+    last_output: Option<OutputEvent>,
+    empty_out: EmptyOut,
+    empty_out_placeholder: String,
+    empty_out_halted: bool,
+    #[cfg(feature = "precompile")]
+    precompiled: Option<precompiled::Precompiled>,
+    trailing_newline: bool,
+    unterminated_output: Option<IoType>,
+}
+
+// embedders run programs on background threads, so the interpreter must stay `Send`
+const _: fn() = || {
+    fn assert_send<T: Send>() {}
+
+    assert_send::<Program>();
+    assert_send::<Interpreter>();
+};
+
+impl<S: fmt::Debug> fmt::Debug for Interpreter<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Interpreter")
+            .field("program", &self.program)
+            .field("state", &self.state)
+            .field("step_no", &self.step_no)
+            .field("escapes", &self.escapes)
+            .field("white_entries", &self.white_entries)
+            .field("max_steps", &self.max_steps)
+            .field("max_ops", &self.max_ops)
+            .field("max_underflows", &self.max_underflows)
+            .field("timeout", &self.timeout)
+            .field("max_stack", &self.max_stack)
+            .field("max_output_bytes", &self.max_output_bytes)
+            .field("escape_attempts", &self.escape_attempts)
+            .field("cycle_detection", &self.cycle_detection)
+            .field("alternate_white_restrictions", &self.alternate_white_restrictions)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Formats the top of the stack along with its depth, e.g. `stack(depth=10423): [..., 3, 7, 42]`
+struct StackDisplay<'a> {
+    stack: &'a [BigInt],
+    limit: usize,
+}
+
+impl fmt::Display for StackDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let depth = self.stack.len();
+        let shown = &self.stack[depth.saturating_sub(self.limit)..];
+
+        write!(f, "stack(depth={}): [", depth)?;
+
+        if shown.len() < depth {
+            write!(f, "...")?;
+
+            if !shown.is_empty() {
+                write!(f, ", ")?;
+            }
+        }
+
+        for (i, v) in shown.iter().enumerate() {
+            if i != 0 {
+                write!(f, ", ")?;
+            }
+
+            write!(f, "{}", v)?;
+        }
+
+        write!(f, "]")
+    }
+}
+
+/// Formats a list of values, e.g. `[3, 7, 42]`
+struct Values<'a>(&'a [BigInt]);
+
+impl fmt::Display for Values<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[")?;
+
+        for (i, v) in self.0.iter().enumerate() {
+            if i != 0 {
+                write!(f, ", ")?;
+            }
+
+            write!(f, "{}", v)?;
+        }
+
+        write!(f, "]")
+    }
+}
+
+/// Whether `n` has more than `max_digits` decimal digits, only formatting it if that is unclear
+fn has_more_digits_than(n: &BigInt, max_digits: usize) -> bool {
+    // 2^(bits - 1) <= |n| < 2^bits bounds the number of digits from both sides
+    let bits = n.bits() as f64;
+    let min_digits = ((bits - 1.0).max(0.0) * std::f64::consts::LOG10_2) as usize + 1;
+    let max_possible_digits = (bits * std::f64::consts::LOG10_2) as usize + 1;
+
+    if min_digits > max_digits {
+        true
+    } else if max_possible_digits <= max_digits {
+        false
+    } else {
+        n.magnitude().to_string().len() > max_digits
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum IoType {
+    Char,
+    Number,
+}
+
+impl fmt::Display for IoType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        #[rustfmt::skip]
+        match self {
+            IoType::Char   => write!(f, "char")?,
+            IoType::Number => write!(f, "number")?,
+        }
+
+        Ok(())
+    }
+}
+
+impl Interpreter {
+    /// Create an interpreter which reads from stdin, writes to stdout, and runs without limits.
+    ///
+    /// Use [`Interpreter::builder`] to configure it differently.
+    pub fn new(program: Program) -> Self {
+        Self::builder(program).build()
+    }
+
+    pub fn builder(program: Program) -> InterpreterBuilder {
+        InterpreterBuilder::new(program)
+    }
+}
+
+impl<S: StackBackend> Interpreter<S> {
+    /// Perform a single step of the program.
+    ///
+    /// A step moves the pointer out of the current block and into the next coloured block,
+    /// executing at most one operation. Sliding across a white region is part of the step
+    /// which entered it and never executes an operation, and the retries made while trying
+    /// to escape a block are not counted as steps of their own. This makes `step_no`, and
+    /// therefore the maximum number of steps, the number of blocks the pointer has left.
+    pub fn step(&mut self) -> Result<StepOutcome, RietError> {
+        let step_no = self.step_no;
+        let step_started = self.time_steps.then(|| self.clock.now());
+
+        let outcome = self.take_step()?;
+
+        if let (StepOutcome::Moved { .. }, Some(depths)) = (&outcome, self.stack_depths.as_mut()) {
+            depths.push(self.state.stack.len());
+        }
+
+        if let Some(step_started) = step_started {
+            let took = self.clock.now().saturating_sub(step_started);
+
+            if !matches!(self.slowest_step, Some((_, slowest)) if slowest >= took) {
+                self.slowest_step = Some((step_no, took));
+            }
+        }
+
+        let halted = matches!(outcome, StepOutcome::Halted(_));
+        if halted && self.trailing_newline {
+            self.terminate_output()?;
+        }
+
+        if halted || self.step_no.is_multiple_of(ELAPSED_SAMPLE_STEPS) {
+            if let Some(started) = self.started {
+                self.elapsed = self.clock.now().saturating_sub(started);
+            }
+        }
+
+        Ok(outcome)
+    }
+
+    fn take_step(&mut self) -> Result<StepOutcome, RietError> {
+        if let Some(reason) = self.check_limits() {
+            return Ok(StepOutcome::Halted(reason));
+        }
+
+        #[cfg(feature = "precompile")]
+        if let Some(compiled) = self.compiled_move() {
+            return self.take_compiled_step(compiled);
+        }
+
+        let curr = self.state.curr_codel;
+        let Point(row, col) = curr;
+        let curr_color = *self
+            .program
+            .get_codel(row, col)
+            .ok_or(RietError::NoColorBlock { row, col })?;
+
+        if curr_color.is_black() {
+            return Err(RietError::InsideBlack);
+        }
+
+        self.program.ensure_color_block(&curr);
+
+        let mut heading = Heading::new(self.state.dp, self.state.cc);
+        let moved = self.next_move(curr, curr_color, &mut heading);
+
+        self.state.dp = heading.dp;
+        self.state.cc = heading.cc;
+        self.escapes += heading.escapes;
+        self.white_entries += heading.white_entries;
+        self.slid_across_white = heading.white_entries > 0;
+
+        let (exit, exit_color, next, next_color) = match moved {
+            Ok(moved) => moved,
+            Err(reason) => return Ok(StepOutcome::Halted(reason)),
+        };
+
+        self.trace_move(exit, exit_color, next, next_color);
+
+        // adjacent codels of the same colour are always part of the same block, so moving into
+        // another block of the same colour means the blocks were built wrongly
+        debug_assert!(
+            exit_color != next_color || exit_color.is_white(),
+            "moved between two {:?} blocks from {:?} to {:?}",
+            exit_color,
+            exit,
+            next
+        );
+
+        let executed = self.action(exit_color, next_color)?;
+
+        Ok(self.finish_step(exit, exit_color, next, next_color, executed))
+    }
+
+    fn trace_move(&self, exit: Point, exit_color: Color, next: Point, next_color: Color) {
+        trace!(
+            "step {:}  {:?} {:?}|{:?} {:?} -> {:?} {:?}|{:?} {:?}",
+            self.step_no,
+            exit,
+            self.state.dp,
+            self.state.cc,
+            exit_color,
+            next,
+            self.state.dp,
+            self.state.cc,
+            next_color,
+        );
+    }
+
+    /// Finish a step which moved from `exit` into `next` and executed `executed`, counting it
+    /// and reporting it to any trace callback
+    fn finish_step(
+        &mut self,
+        exit: Point,
+        exit_color: Color,
+        next: Point,
+        next_color: Color,
+        executed: Option<Operation>,
+    ) -> StepOutcome {
+        if executed.is_some() {
+            self.ops_executed += 1;
+        }
+
+        // backends other than Vec copy the whole stack to show it, so only do so when needed
+        let trace_stack = self.trace_full_stack && log_enabled!(Level::Trace);
+        if self.trace_callback.is_some() || trace_stack {
+            let stack = self.state.stack.values();
+
+            if let Some(callback) = self.trace_callback.as_mut() {
+                callback(&TraceEvent {
+                    step_no: self.step_no,
+                    from: exit,
+                    from_color: exit_color,
+                    to: next,
+                    to_color: next_color,
+                    dp: self.state.dp,
+                    cc: self.state.cc,
+                    executed,
+                    stack: &stack,
+                });
+            }
+
+            if trace_stack {
+                trace!(
+                    "{}",
+                    StackDisplay {
+                        stack: &stack,
+                        limit: self.trace_stack_limit,
+                    }
+                );
+            }
+        }
+
+        self.step_no += 1;
+        self.state.curr_codel = next;
+
+        StepOutcome::Moved { executed }
+    }
+
+    /// Find how the pointer leaves the block containing `curr`, without changing the state.
+    ///
+    /// Returns the codel the pointer leaves from and its colour, followed by the coloured codel
+    /// it moves into and its colour. `heading` is updated to the pointer's new direction.
+    fn next_move(
+        &self,
+        curr: Point,
+        curr_color: Color,
+        heading: &mut Heading,
+    ) -> Result<(Point, Color, Point, Color), HaltReason> {
+        if curr_color.is_white() {
+            self.slide(curr, heading)
+        } else {
+            match self.escape(curr, heading)? {
+                (next, Color::White) => self.slide(next, heading),
+                (next, next_color) => Ok((curr, curr_color, next, next_color)),
+            }
+        }
+    }
+
+    /// The codel the pointer will move into on the next step, and the operation it will execute.
+    ///
+    /// Moves which don't execute an operation give [`Operation::Noop`], and `None` is returned
+    /// if the pointer can't move at all. This doesn't change the state of the interpreter, and
+    /// ignores any limits the next step could halt on.
+    pub fn peek_next_operation(&self) -> Option<(Point, Operation)> {
+        let (exit_color, next, next_color) = self.peek_next_move()?;
+
+        let op = exit_color
+            .transition_with(&next_color, &self.operation_table)
+            .operation()
+            .unwrap_or(Operation::Noop);
+
+        Some((next, op))
+    }
+
+    /// The colour the pointer will leave from on the next step, followed by the codel it will
+    /// move into and its colour.
+    ///
+    /// The colour left from is the current block's, or white if the step slides across white.
+    /// Like [`Interpreter::peek_next_operation`], this doesn't change the state of the
+    /// interpreter.
+    pub fn peek_next_move(&self) -> Option<(Color, Point, Color)> {
+        let curr = self.state.curr_codel;
+        let curr_color = *self.program.get_codel(*curr.row(), *curr.col())?;
+
+        if curr_color.is_black() || self.program.get_color_block(&curr).is_none() {
+            return None;
+        }
+
+        let mut heading = Heading::new(self.state.dp, self.state.cc);
+        let (_, exit_color, next, next_color) =
+            self.next_move(curr, curr_color, &mut heading).ok()?;
+
+        Some((exit_color, next, next_color))
+    }
+
+    /// Check whether the program has passed any of the limits it was configured with
+    fn check_limits(&mut self) -> Option<HaltReason> {
+        if matches!(self.max_steps, Some(max_steps) if self.step_no >= max_steps) {
+            return Some(HaltReason::MaxSteps);
+        }
+
+        if matches!(self.max_ops, Some(max_ops) if self.ops_executed >= max_ops) {
+            return Some(HaltReason::MaxOps);
+        }
+
+        // the clock is only read once to start with, unless there is a timeout to check
+        let started = match self.started {
+            Some(started) => started,
+            None => *self.started.insert(self.clock.now()),
+        };
+
+        if let Some(timeout) = self.timeout {
+            if self.clock.now().saturating_sub(started) > timeout {
+                return Some(HaltReason::Timeout);
+            }
+        }
+
+        if matches!(self.max_underflows, Some(max) if self.underflows > max) {
+            return Some(HaltReason::UnderflowLimit);
+        }
+
+        if matches!(self.max_stack, Some(max_stack) if self.state.stack.len() > max_stack) {
+            return Some(HaltReason::StackLimit);
+        }
+
+        if matches!(self.max_output_bytes, Some(max_bytes) if self.output_bytes > max_bytes) {
+            return Some(HaltReason::OutputLimit);
+        }
+
+        if self.empty_out_halted {
+            return Some(HaltReason::EmptyOut);
+        }
+
+        if self.cycle_detection {
+            let mut hasher = DefaultHasher::new();
+            self.state.hash(&mut hasher);
+
+            if !self.seen_states.insert(hasher.finish()) {
+                return Some(HaltReason::Cycle);
+            }
+        }
+
+        None
+    }
+
+    /// Find the codel the pointer moves into when leaving the coloured block containing `from`,
+    /// toggling the CC and rotating the DP as the spec requires when it is restricted.
+    fn escape(&self, from: Point, heading: &mut Heading) -> Result<(Point, Color), HaltReason> {
+        for tries in 0..self.escape_attempts {
+            let next_move = self.program.next_move(&from, heading.dp, heading.cc);
+
+            if let Some((_, next, next_color)) = next_move {
+                return Ok((next, next_color));
+            }
+
+            heading.escapes += 1;
+
+            if tries % 2 == 0 {
+                heading.cc = heading.cc.toggle();
+            } else {
+                heading.dp = heading.dp.rotate_clockwise();
+            }
+        }
+
+        trace!("Attempted to exit block {} times, exiting.", self.escape_attempts);
+
+        Err(HaltReason::Trapped)
+    }
+
+    /// Slide in a straight line across the white region containing `start`.
+    ///
+    /// Returns the last white codel visited and its colour, followed by the coloured codel
+    /// the pointer slides into and its colour.
+    fn slide(
+        &self,
+        start: Point,
+        heading: &mut Heading,
+    ) -> Result<(Point, Color, Point, Color), HaltReason> {
+        trace!("Entering white block at {:?} {:?}|{:?}", start, heading.dp, heading.cc);
+
+        heading.white_entries += 1;
+
+        let mut curr = start;
+
+        let mut restrictions = 0;
+
+        // go in a straight line until we encounter a restriction or a non-white pixel, when
+        // alternating, whether the next restriction toggles or rotates is part of the state
+        let mut seen_states: HashSet<(Point, DirectionPointer, CodelChooser, bool)> =
+            Default::default();
+
+        loop {
+            let toggle_next = !self.alternate_white_restrictions || restrictions % 2 == 0;
+
+            if !seen_states.insert((curr, heading.dp, heading.cc, toggle_next)) {
+                trace!("Could not escape white block - exiting");
+
+                break Err(HaltReason::WhiteLock);
+            }
+
+            if seen_states.len() > self.max_white_states {
+                warn!(
+                    "Visited more than {} states sliding through a white block, assuming it can't be escaped",
+                    self.max_white_states
+                );
+
+                break Err(HaltReason::WhiteLock);
+            }
+
+            let next_codel = curr.next_in_direction(heading.dp, &self.program);
+            let maybe_next_color = next_codel
+                .and_then(|Point(row, col)| self.program.get_codel(row, col).copied());
+
+            match (next_codel, maybe_next_color) {
+                // restricted
+                (None, _) | (_, None) | (_, Some(Color::Black)) => {
+                    if !self.alternate_white_restrictions {
+                        heading.cc = heading.cc.toggle();
+                        heading.dp = heading.dp.rotate_clockwise();
+                    } else if toggle_next {
+                        heading.cc = heading.cc.toggle();
+                    } else {
+                        heading.dp = heading.dp.rotate_clockwise();
+                    }
+
+                    restrictions += 1;
+
+                    trace!(
+                        "white restriction at {:?}, now heading {:?}|{:?}",
+                        curr,
+                        heading.dp,
+                        heading.cc
+                    );
+                }
+
+                (Some(next), Some(Color::White)) => {
+                    trace!("sliding {:?} to {:?}", heading.dp, next);
+
+                    curr = next;
+                }
+
+                (Some(next), Some(next_color)) => {
+                    trace!("white cell(s) crossed, continuing at {:?}", next);
+
+                    break Ok((curr, Color::White, next, next_color));
+                }
+            }
+        }
+    }
+
+    /// Execute the operation for moving between blocks of the given colours, returning it
+    fn action(
+        &mut self,
+        curr_color: Color,
+        next_color: Color,
+    ) -> Result<Option<Operation>, RietError> {
+        let op = match curr_color
+            .transition_with(&next_color, &self.operation_table)
+            .operation()
+        {
+            Some(op) => op,
+            None => return Ok(None),
+        };
+
+        self.execute(op)?;
+
+        Ok(Some(op))
+    }
+
+    /// Apply a single operation to the stack and pointers without moving between codels.
+    ///
+    /// `Push` pushes the number of codels in the block containing the current codel, and does
+    /// nothing if the current codel isn't in the program. Fails if reading the input or writing
+    /// the output fails.
+    pub fn execute(&mut self, op: Operation) -> Result<(), RietError> {
+        let traced = log_enabled!(Level::Trace) && self.traces_operation(op);
+
+        if traced && self.trace_full_stack {
+            match op {
+                Operation::Noop => {}
+                Operation::Push => match self.current_block_value() {
+                    Some(value) => trace!("action: push, value {:?}", value),
+                    None => trace!("action: push, no colour block"),
+                },
+                _ => trace!("action: {}", op),
+            }
+        }
+
+        // the values the operation will take, to show how it changed the stack
+        let depth = self.state.stack.len();
+        let taken = if traced && !self.trace_full_stack {
+            let values = self.state.stack.values();
+            values[depth - op.arity().min(depth)..].to_vec()
+        } else {
+            vec![]
+        };
+
+        #[rustfmt::skip]
+        match op {
+            Operation::Noop      => {},
+            Operation::Push      => { self.push_block_value(); },
+            Operation::Pop       => { self.pop(); },
+
+            Operation::Add       => { self.add(); },
+            Operation::Subtract  => { self.subtract(); },
+            Operation::Multiply  => { self.multiply(); },
+
+            Operation::Divide    => { self.divide(); },
+            Operation::Mod       => { self.r#mod(); },
+            Operation::Not       => { self.not(); },
+
+            Operation::Greater   => { self.greater(); },
+            Operation::Pointer   => { self.pointer(); },
+            Operation::Switch    => { self.switch(); },
+
+            Operation::Duplicate => { self.duplicate(); },
+            Operation::Roll      => { self.roll(); },
+            Operation::InNumber  => { self.r#in(IoType::Number)?; },
+
+            Operation::InChar    => { self.r#in(IoType::Char)?; },
+            Operation::OutNumber => { self.out(IoType::Number)?; },
+            Operation::OutChar   => { self.out(IoType::Char)?; },
+        }
+
+        if traced && !self.trace_full_stack {
+            self.trace_stack_delta(op, depth, &taken);
+        }
+
+        Ok(())
+    }
+
+    /// Trace how `op` changed the stack, given its depth and the values `op` would take before
+    fn trace_stack_delta(&self, op: Operation, depth: usize, taken: &[BigInt]) {
+        let values = self.state.stack.values();
+        let kept = depth - taken.len();
+        let added = values.get(kept..).unwrap_or_default();
+
+        match (op, taken) {
+            (Operation::Noop, _) => {}
+            // rolling moves values below the arguments, so describe it instead
+            (Operation::Roll, [depth, rolls]) => {
+                trace!("action: roll(depth={}, rolls={})", depth, rolls)
+            }
+            _ => trace!("action: {} {} -> {}", op, Values(taken), Values(added)),
+        }
+    }
+
+    /// Whether the trace output for executing `op` should be emitted
+    fn traces_operation(&self, op: Operation) -> bool {
+        !matches!(&self.trace_operations, Some(ops) if !ops.contains(&op))
+    }
+
+    /// The number of codels in the block containing the current codel, if it has one
+    fn current_block_value(&self) -> Option<usize> {
+        self.program
+            .get_color_block(&self.state.curr_codel)
+            .map(|cb| cb.num_codels())
+    }
+
+    /// Reset the interpreter to the state it started in, so the program can be run again.
+    ///
+    /// The program, its input and outputs, and the interpreter's configuration are kept.
+    pub fn reset(&mut self) {
+        self.state = Default::default();
+        self.step_no = 0;
+        self.ops_executed = 0;
+        self.underflows = 0;
+        self.escapes = 0;
+        self.white_entries = 0;
+        self.slid_across_white = false;
+        self.output_bytes = 0;
+        self.seen_states.clear();
+        self.started = None;
+        self.elapsed = Duration::ZERO;
+        self.slowest_step = None;
+        self.last_output = None;
+        self.empty_out_halted = false;
+        self.unterminated_output = None;
+        self.max_stack_depth = 0;
+
+        if let Some(provenance) = self.provenance.as_mut() {
+            provenance.clear();
+        }
+
+        if let Some(depths) = self.stack_depths.as_mut() {
+            depths.clear();
+        }
+
+        for v in self.initial_stack.clone() {
+            self.push_value(v);
+        }
+    }
+
+    /// Push `values` onto the stack, from bottom to top, before the program starts.
+    ///
+    /// This lets part of a program be run without an image which builds up the stack first.
+    /// The values are pushed again whenever the interpreter is [reset](Interpreter::reset).
+    pub fn with_initial_stack(mut self, values: Vec<BigInt>) -> Self {
+        for v in values.iter().cloned() {
+            self.push_value(v);
+        }
+
+        self.initial_stack.extend(values);
+        self
+    }
+
+    /// The state of the pointer and stack, which the run can later be resumed from
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            dp: self.state.dp,
+            cc: self.state.cc,
+            curr_codel: self.state.curr_codel,
+            stack: self.state.stack.values().into_owned(),
+        }
+    }
+
+    /// Restore the pointer and stack from `snapshot`, continuing the run as if `step_no` steps
+    /// had been taken, e.g. to resume a long run from a checkpoint or a step in a golden trace.
+    ///
+    /// The snapshot's codel must be a coloured or white codel in the program. The values on
+    /// the stack are attributed to `step_no` when provenance is tracked.
+    pub fn resume_at(&mut self, snapshot: Snapshot, step_no: usize) -> Result<(), RietError> {
+        let Point(row, col) = snapshot.curr_codel;
+
+        match self.program.get_codel(row, col) {
+            None => return Err(RietError::NoColorBlock { row, col }),
+            Some(color) if color.is_black() => return Err(RietError::InsideBlack),
+            Some(_) => {}
+        }
+
+        self.state = PietState {
+            dp: snapshot.dp,
+            cc: snapshot.cc,
+            curr_codel: snapshot.curr_codel,
+            stack: S::default(),
+        };
+        self.step_no = step_no;
+        self.slid_across_white = false;
+        self.seen_states.clear();
+
+        if let Some(provenance) = self.provenance.as_mut() {
+            provenance.clear();
+        }
+
+        for v in snapshot.stack {
+            self.push_value(v);
+        }
+
+        Ok(())
+    }
+
+    /// The codel the pointer is currently in
+    pub fn current_codel(&self) -> Point {
+        self.state.curr_codel
+    }
+
+    /// The direction the pointer will move in on the next step
+    pub fn dp(&self) -> DirectionPointer {
+        self.state.dp
+    }
+
+    /// The side of the block's edge the pointer will leave from on the next step
+    pub fn cc(&self) -> CodelChooser {
+        self.state.cc
+    }
+
+    /// The colour of the codel the pointer is currently in, or `None` if it is outside the
+    /// program
+    pub fn current_color(&self) -> Option<Color> {
+        let Point(row, col) = self.state.curr_codel;
+
+        self.program.get_codel(row, col).copied()
+    }
+
+    /// Whether the pointer is in a white region or its last step slid across one.
+    ///
+    /// A step slides all the way across a white region, so the pointer only rests on white
+    /// when the program starts in it or gets stuck in it, but this lets a visualisation show
+    /// the moves which didn't execute an operation because they passed through white.
+    pub fn in_white_traversal(&self) -> bool {
+        self.slid_across_white || matches!(self.current_color(), Some(color) if color.is_white())
+    }
+
+    /// The program being interpreted
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+
+    /// The stack
+    pub fn stack(&self) -> &S {
+        &self.state.stack
+    }
+
+    /// The step which pushed each value on the stack, from bottom to top, if it is tracked.
+    ///
+    /// Values moved by `roll` keep the step which pushed them, while every other operation's
+    /// results are attributed to the step which executed it.
+    pub fn provenance(&self) -> Option<&[usize]> {
+        self.provenance.as_deref()
+    }
+
+    /// The depth of the stack after each step which moved the pointer, if it is recorded
+    pub fn stack_depths(&self) -> Option<&[usize]> {
+        self.stack_depths.as_deref()
+    }
+
+    /// The deepest the stack has been since the interpreter was built or last reset
+    pub fn max_stack_depth_reached(&self) -> usize {
+        self.max_stack_depth
+    }
+
+    /// Panic unless the stack, from bottom to top, is exactly `expected`
+    #[cfg(any(test, feature = "testing"))]
+    #[track_caller]
+    pub fn assert_stack(&self, expected: &[i64]) {
+        let values = self.state.stack.values();
+        let expected: Vec<BigInt> = expected.iter().map(|&v| v.into()).collect();
+
+        if *values != *expected {
+            let common = values.iter().zip(&expected).take_while(|(a, b)| a == b).count();
+
+            panic!(
+                "stack mismatch from position {}:\n  expected: {}\n    actual: {}",
+                common,
+                Values(&expected),
+                Values(&values)
+            );
+        }
+    }
+
+    /// Statistics about the program's execution so far
+    pub fn summary(&self) -> Summary {
+        Summary {
+            steps: self.step_no,
+            operations: self.ops_executed,
+            underflows: self.underflows,
+            escapes: self.escapes,
+            white_entries: self.white_entries,
+            max_stack_depth: self.max_stack_depth,
+            elapsed: self.elapsed,
+            slowest_step: self.slowest_step,
+        }
+    }
+
+    /// Run the program until it halts, returning the reason it stopped
+    pub fn run(&mut self) -> Result<HaltReason, RietError> {
+        self.run_with(RunConfig::default())
+    }
+
+    /// Run the program until it halts or reaches any of the limits in `config`
+    pub fn run_with(&mut self, config: RunConfig) -> Result<HaltReason, RietError> {
+        let started = config.timeout.map(|_| self.clock.now());
+        let cycle_detection = self.cycle_detection;
+
+        // only remember the states from this run unless the interpreter always detects cycles
+        if config.cycle_detection && !cycle_detection {
+            self.seen_states.clear();
+            self.cycle_detection = true;
+        }
+
+        let halted = loop {
+            if matches!(config.max_steps, Some(max_steps) if self.step_no >= max_steps) {
+                break Ok(HaltReason::MaxSteps);
+            }
+
+            if let (Some(timeout), Some(started)) = (config.timeout, started) {
+                if self.clock.now().saturating_sub(started) > timeout {
+                    break Ok(HaltReason::Timeout);
+                }
+            }
+
+            match self.step() {
+                Ok(StepOutcome::Halted(reason)) => break Ok(reason),
+                Ok(StepOutcome::Moved { .. }) => {}
+                Err(e) => break Err(e),
+            }
+        };
+
+        self.cycle_detection = cycle_detection;
+
+        if let Ok(reason) = halted {
+            info!("Program stopping: {}", reason);
+        }
+
+        halted
+    }
+
+    /// Perform up to `n` steps of the program, stopping early if it halts.
+    ///
+    /// This returns the outcome of the last step taken, and if `n` is zero no step is taken and
+    /// the pointer is reported to have moved without executing anything.
+    pub fn step_n(&mut self, n: usize) -> Result<StepOutcome, RietError> {
+        let mut outcome = StepOutcome::Moved { executed: None };
+
+        for _ in 0..n {
+            outcome = self.step()?;
+
+            if let StepOutcome::Halted(_) = outcome {
+                break;
+            }
+        }
+
+        Ok(outcome)
+    }
+
+    /// Run the program until it halts or has taken `max_steps` steps
+    pub fn run_until(&mut self, max_steps: usize) -> Result<HaltReason, RietError> {
+        self.run_with(RunConfig {
+            max_steps: Some(max_steps),
+            ..Default::default()
+        })
+    }
+
+    /// Run the program until it writes a value, returning it, or `None` if it halts first.
+    ///
+    /// The value is still written to the output as normal.
+    pub fn run_until_output(&mut self) -> Result<Option<OutputEvent>, RietError> {
+        self.last_output = None;
+
+        loop {
+            if let StepOutcome::Halted(_) = self.step()? {
+                return Ok(None);
+            }
+
+            if let Some(event) = self.last_output.take() {
+                return Ok(Some(event));
+            }
+        }
+    }
+
+    /// Push `v`, recording that the current step produced it if provenance is tracked
+    fn push_value(&mut self, v: BigInt) {
+        self.state.stack.push(v);
+        self.max_stack_depth = self.max_stack_depth.max(self.state.stack.len());
+
+        if let Some(provenance) = self.provenance.as_mut() {
+            provenance.push(self.step_no);
+        }
+    }
+
+    /// Pop the top value, along with its provenance if it is tracked
+    fn pop_value(&mut self) -> Option<BigInt> {
+        if let Some(provenance) = self.provenance.as_mut() {
+            provenance.pop();
+        }
+
+        self.state.stack.pop()
+    }
+
+    /// Rotate the top `depth` values, and their provenance if it is tracked, left by `mid`
+    fn rotate_values(&mut self, depth: usize, mid: usize) {
+        self.state.stack.rotate_left(depth, mid);
+
+        if let Some(provenance) = self.provenance.as_mut() {
+            let len = provenance.len();
+
+            provenance[len - depth..].rotate_left(mid);
+        }
+    }
+
+    /// Count an operation which failed as there weren't enough values on the stack
+    fn stack_underflow(&mut self, op: &str) {
+        self.underflows += 1;
+
+        info!("{} failed: stack underflow", op);
+    }
+
+    fn push(&mut self, v: usize) {
+        self.push_value(v.into());
+    }
+
+    fn push_block_value(&mut self) -> Option<()> {
+        let value = self.current_block_value();
+
+        if value.is_none() {
+            info!("push failed: the current codel isn't part of a colour block");
+        }
+
+        self.push(value?);
+
+        Some(())
+    }
+
+    fn pop(&mut self) {
+        if self.pop_value().is_none() {
+            self.stack_underflow("pop");
+        }
+    }
+
+    fn add(&mut self) -> Option<()> {
+        if self.state.stack.len() >= 2 {
+            let a = self.pop_value()?;
+            let b = self.pop_value()?;
+
+            self.push_value(a + b);
+        } else {
+            self.stack_underflow("add");
+        }
+
+        Some(())
+    }
+
+    fn subtract(&mut self) -> Option<()> {
+        if self.state.stack.len() >= 2 {
+            let a = self.pop_value()?;
+            let b = self.pop_value()?;
+
+            self.push_value(b - a);
+        } else {
+            self.stack_underflow("subtract");
+        }
+
+        Some(())
+    }
+
+    fn multiply(&mut self) -> Option<()> {
+        if self.state.stack.len() >= 2 {
+            let a = self.pop_value()?;
+            let b = self.pop_value()?;
+
+            self.push_value(a * b);
+        } else {
+            self.stack_underflow("multiply");
+        }
+
+        Some(())
+    }
+
+    fn divide(&mut self) -> Option<()> {
+        if self.state.stack.len() >= 2 {
+            let a = self.pop_value()?;
+            let b = self.pop_value()?;
+
+            if a.is_zero() {
+                info!("divide failed: division by zero");
+
+                return None;
+            }
+
+            self.push_value(b / a);
+        } else {
+            self.stack_underflow("divide");
+        }
+
+        Some(())
+    }
+
+    fn r#mod(&mut self) -> Option<()> {
+        if self.state.stack.len() >= 2 {
+            let a = &self.pop_value()?;
+            let b = &self.pop_value()?;
+
+            if a.is_zero() {
+                info!("mod failed: division by zero");
+
+                return None;
+            }
+
+            let res = (a + (b % a)) % a;
+
+            self.push_value(res);
+        } else {
+            self.stack_underflow("mod");
+        }
+
+        Some(())
+    }
+
+    fn not(&mut self) -> Option<()> {
+        let top = self.pop_value();
+
+        if top.is_none() {
+            self.stack_underflow("not");
+        }
+
+        let val = top?;
+
+        if val.is_zero() {
+            self.push_value(One::one());
+        } else {
+            self.push_value(Zero::zero());
+        }
+
+        Some(())
+    }
+
+    fn greater(&mut self) -> Option<()> {
+        if self.state.stack.len() >= 2 {
+            let a = self.pop_value()?;
+            let b = self.pop_value()?;
+
+            if b > a {
+                self.push_value(One::one());
+            } else {
+                self.push_value(Zero::zero());
+            }
+        } else {
+            self.stack_underflow("greater");
+        }
+
+        Some(())
+    }
+
+    fn pointer(&mut self) -> Option<()> {
+        let top = self.pop_value();
+
+        if top.is_none() {
+            self.stack_underflow("pointer");
+        }
+
+        let n = top?;
+
+        if n.bits() > LARGE_ARGUMENT_BITS {
+            info!("pointer: argument is suspiciously large ({} bits)", n.bits());
+        }
+
+        // the lowest two bits of the two's complement are the number of turns modulo 4, which
+        // makes negative arguments turn anticlockwise without dividing huge numbers
+        let turns = u8::from(n.bit(1)) * 2 + u8::from(n.bit(0));
+
+        for _ in 0..turns {
+            self.state.dp = self.state.dp.rotate_clockwise();
+        }
+
+        Some(())
+    }
+
+    fn switch(&mut self) -> Option<()> {
+        let top = self.pop_value();
+
+        if top.is_none() {
+            self.stack_underflow("switch");
+        }
+
+        let n = top?;
+
+        if n.bits() > LARGE_ARGUMENT_BITS {
+            info!("switch: argument is suspiciously large ({} bits)", n.bits());
+        }
+
+        if n.bit(0) {
+            self.state.cc = self.state.cc.toggle();
+        }
+
+        Some(())
+    }
+
+    fn duplicate(&mut self) -> Option<()> {
+        let top = self.state.stack.peek().cloned();
+
+        if top.is_none() {
+            self.stack_underflow("duplicate");
+        }
+
+        self.push_value(top?);
+
+        Some(())
+    }
+
+    fn roll(&mut self) -> Option<()> {
+        if self.state.stack.len() >= 2 {
+            let rolls = self.pop_value()?;
+            let depth: usize = {
+                let d = self.pop_value()?;
+
+                if d.is_negative() {
+                    info!("roll failed: negative depth");
+
+                    return None;
+                }
+
+                let d_us = d.try_into().ok();
+
+                if d_us.is_none() {
+                    info!("roll failed: depth exceeds maximum value of usize")
+                }
+
+                d_us?
+            };
+
+            if depth > self.state.stack.len() {
+                info!("roll failed: depth exceeds stack size");
+
+                return None;
+            }
+
+            // rolling no values does nothing, and would divide by zero below
+            if depth == 0 {
+                return Some(());
+            }
+
+            // a positive roll buries the top value `rolls` deep, which rotates the values right
+            let mid = (rolls.magnitude() % depth).to_usize()?;
+            if rolls.is_negative() {
+                self.rotate_values(depth, mid);
+            } else {
+                self.rotate_values(depth, (depth - mid) % depth);
+            }
+        } else {
+            self.stack_underflow("roll");
+        }
+
+        Some(())
+    }
+
+    /// Read a value of `iotype` and push it, failing only if reading the input fails
+    fn r#in(&mut self, iotype: IoType) -> io::Result<()> {
+        // show a prompt on stderr, so it never ends up in the program's output
+        if self.prompt {
+            let stderr = io::stderr();
+            let mut stderr = stderr.lock();
+            write!(stderr, "> ")?;
+            stderr.flush()?;
+        }
+
+        // both kinds of input read from the same buffer, so they can be freely interleaved
+        let value = match iotype {
+            IoType::Char => read_char(&mut self.input)?.map(|c| (c as u32).into()),
+            IoType::Number => read_number(&mut self.input)?,
+        };
+
+        // the program may behave differently now it has new input
+        self.seen_states.clear();
+
+        match value {
+            Some(value) => self.push_value(value),
+            None => match iotype {
+                IoType::Char => info!("in(char) failed: input contained no characters"),
+                IoType::Number => info!("in(number) failed: input was not a valid number"),
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Pop a value and write it as `iotype`, failing only if writing the output fails
+    fn out(&mut self, iotype: IoType) -> io::Result<()> {
+        let n = match self.pop_value() {
+            Some(n) => n,
+            None => {
+                self.stack_underflow(&format!("out({})", iotype));
+
+                return self.out_empty(iotype);
+            }
+        };
+
+        let (bytes, event) = match iotype {
+            IoType::Char => {
+                let encoded = self.char_encoding.encode(&n).and_then(|bytes| {
+                    let c = self.char_encoding.to_char(&n)?;
+                    Some((bytes, OutputEvent::Char(c)))
+                });
+
+                match encoded {
+                    Some(encoded) => encoded,
+                    None => {
+                        info!(
+                            "out(char) failed: value popped off the stack was not a valid {} char",
+                            self.char_encoding
+                        );
+
+                        return Ok(());
+                    }
+                }
+            }
+            IoType::Number => {
+                if let Some(max_digits) = self.max_number_digits {
+                    if has_more_digits_than(&n, max_digits) {
+                        warn!(
+                            "out(number) skipped: value has more than {} digits",
+                            max_digits
+                        );
+
+                        return Ok(());
+                    }
+                }
+
+                (n.to_string().into_bytes(), OutputEvent::Number(n))
+            }
+        };
+
+        self.write_output(iotype, &bytes, Some(event))
+    }
+
+    /// Handle `out` being executed with an empty stack as configured
+    fn out_empty(&mut self, iotype: IoType) -> io::Result<()> {
+        match self.empty_out {
+            EmptyOut::Ignore => Ok(()),
+            EmptyOut::Error => {
+                error!(
+                    "out({}) failed at step {}: the stack is empty",
+                    iotype, self.step_no
+                );
+                self.empty_out_halted = true;
+
+                Ok(())
+            }
+            EmptyOut::Placeholder => {
+                let placeholder = self.empty_out_placeholder.clone();
+
+                self.write_output(iotype, placeholder.as_bytes(), None)
+            }
+        }
+    }
+
+    /// Write the bytes output by `out`, passing the value they encode to the output callback
+    fn write_output(
+        &mut self,
+        iotype: IoType,
+        bytes: &[u8],
+        event: Option<OutputEvent>,
+    ) -> io::Result<()> {
+        // count the output even when it isn't written, so the program halts before its next step
+        self.output_bytes += bytes.len();
+
+        if matches!(self.max_output_bytes, Some(max_bytes) if self.output_bytes > max_bytes) {
+            info!("out({}) failed: the output limit was reached", iotype);
+
+            return Ok(());
+        }
+
+        if let (Some(callback), Some(event)) = (self.output_callback.as_mut(), &event) {
+            callback(event.clone());
+        }
+
+        if event.is_some() {
+            self.last_output = event;
+        }
+
+        let output = self.output_for(iotype);
+
+        // failing to write the output is a runtime error, which halts the program
+        output.write_all(bytes)?;
+        output.flush()?;
+
+        if let Some(&last) = bytes.last() {
+            self.unterminated_output = (last != b'\n').then_some(iotype);
+        }
+
+        Ok(())
+    }
+
+    /// The output `out` writes values of `iotype` to
+    fn output_for(&mut self, iotype: IoType) -> &mut Box<dyn Write + Send> {
+        match iotype {
+            IoType::Char => self.char_output.as_mut(),
+            IoType::Number => self.number_output.as_mut(),
+        }
+        .unwrap_or(&mut self.output)
+    }
+
+    /// End the output with a newline if the last thing written didn't, so it isn't left on
+    /// the same line as whatever is printed after the program
+    fn terminate_output(&mut self) -> io::Result<()> {
+        if let Some(iotype) = self.unterminated_output.take() {
+            let output = self.output_for(iotype);
+
+            output.write_all(b"\n")?;
+            output.flush()?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::program::tests::random_programs;
+
+    use std::sync::{Arc, Mutex};
+
+    /// Arguments for pointer and switch: small, negative, and far too big for a machine word
+    fn arguments() -> Vec<BigInt> {
+        let huge = BigInt::one() << 100_000;
+
+        let mut arguments: Vec<BigInt> = (-9..=9).map(BigInt::from).collect();
+        for offset in -5..=5 {
+            arguments.push(&huge + offset);
+            arguments.push(-&huge + offset);
+        }
+        arguments.push(BigInt::from(u64::MAX) + 1);
+        arguments.push(BigInt::from(i64::MIN) - 1);
+
+        arguments
+    }
+
+    /// Execute `op` with `n` as the only value on the stack
+    fn execute_with(op: Operation, n: &BigInt) -> Interpreter {
+        let program = Program::from_ascii("R\n").unwrap();
+        let mut interpreter = Interpreter::new(program).with_initial_stack(vec![n.clone()]);
+        interpreter.execute(op).unwrap();
+        interpreter.assert_stack(&[]);
+
+        interpreter
+    }
+
+    /// Execute `op` on a stack holding `stack`, from bottom to top
+    fn execute_on(stack: &[i64], op: Operation) -> Interpreter {
+        let program = Program::from_ascii("R\n").unwrap();
+        let stack = stack.iter().map(|&v| v.into()).collect();
+        let mut interpreter = Interpreter::new(program).with_initial_stack(stack);
+        interpreter.execute(op).unwrap();
+
+        interpreter
+    }
+
+    #[test]
+    fn arithmetic_takes_the_second_value_then_the_top() {
+        use Operation::*;
+
+        execute_on(&[1, 2, 3], Add).assert_stack(&[1, 5]);
+        execute_on(&[5, 3], Subtract).assert_stack(&[2]);
+        execute_on(&[3, 5], Subtract).assert_stack(&[-2]);
+        execute_on(&[-4, 3], Multiply).assert_stack(&[-12]);
+        execute_on(&[7, 2], Divide).assert_stack(&[3]);
+        execute_on(&[-7, 2], Divide).assert_stack(&[-3]);
+        execute_on(&[3, 2], Greater).assert_stack(&[1]);
+        execute_on(&[2, 3], Greater).assert_stack(&[0]);
+        execute_on(&[2, 2], Greater).assert_stack(&[0]);
+    }
+
+    #[test]
+    fn mod_takes_the_sign_of_the_divisor() {
+        execute_on(&[7, 3], Operation::Mod).assert_stack(&[1]);
+        execute_on(&[-7, 3], Operation::Mod).assert_stack(&[2]);
+        execute_on(&[7, -3], Operation::Mod).assert_stack(&[-2]);
+        execute_on(&[-7, -3], Operation::Mod).assert_stack(&[-1]);
+    }
+
+    #[test]
+    fn stack_operations() {
+        use Operation::*;
+
+        execute_on(&[], Push).assert_stack(&[1]);
+        execute_on(&[1, 2], Pop).assert_stack(&[1]);
+        execute_on(&[0], Not).assert_stack(&[1]);
+        execute_on(&[-5], Not).assert_stack(&[0]);
+        execute_on(&[1, 2], Duplicate).assert_stack(&[1, 2, 2]);
+
+        // a roll by one buries the top value, and a roll by minus one brings it back up
+        execute_on(&[1, 2, 3, 3, 1], Roll).assert_stack(&[3, 1, 2]);
+        execute_on(&[3, 1, 2, 3, -1], Roll).assert_stack(&[1, 2, 3]);
+        execute_on(&[1, 2, 3, 4, 3, 2], Roll).assert_stack(&[1, 3, 4, 2]);
+        execute_on(&[1, 2, 3, 4, 3, 4], Roll).assert_stack(&[1, 4, 2, 3]);
+        execute_on(&[1, 2, 3, 4, 3, -4], Roll).assert_stack(&[1, 3, 4, 2]);
+        // rolling by a multiple of the depth, or to no depth, leaves the values where they are
+        execute_on(&[1, 2, 3, 4, 3, 6], Roll).assert_stack(&[1, 2, 3, 4]);
+        execute_on(&[1, 2, 3, 4, 3, -3], Roll).assert_stack(&[1, 2, 3, 4]);
+        execute_on(&[1, 2, 3, 0, 7], Roll).assert_stack(&[1, 2, 3]);
+        // a negative depth, or one deeper than the stack, only takes the arguments
+        execute_on(&[1, 2, -1, 1], Roll).assert_stack(&[1, 2]);
+        execute_on(&[1, 2, 3, 1], Roll).assert_stack(&[1, 2]);
+    }
+
+    #[test]
+    fn push_outside_the_program_leaves_the_stack_alone() {
+        let program = Program::from_ascii("R\n").unwrap();
+        let mut interpreter = Interpreter::new(program).with_initial_stack(vec![4.into()]);
+        interpreter.state.curr_codel = Point(5, 5);
+
+        interpreter.execute(Operation::Push).unwrap();
+        interpreter.assert_stack(&[4]);
+    }
+
+    #[test]
+    fn peeking_shows_whether_the_next_move_slides_across_white() {
+        let mut interpreter = Interpreter::new(Program::from_ascii("R.G\n1..\n").unwrap());
+        assert_eq!(
+            interpreter.peek_next_move(),
+            Some((Color::White, Point(0, 2), Color::Green))
+        );
+        assert_eq!(
+            interpreter.peek_next_operation(),
+            Some((Point(0, 2), Operation::Noop))
+        );
+
+        interpreter.state.curr_codel = Point(1, 0);
+        interpreter.state.dp = DirectionPointer::Up;
+        assert_eq!(
+            interpreter.peek_next_move(),
+            Some((Color::DarkRed, Point(0, 0), Color::Red))
+        );
+        assert_eq!(
+            interpreter.peek_next_operation(),
+            Some((Point(0, 0), Operation::Pop))
+        );
+    }
+
+    #[test]
+    fn current_color_is_none_outside_the_program() {
+        let mut interpreter = Interpreter::new(Program::from_ascii("R\n").unwrap());
+        assert_eq!(interpreter.current_color(), Some(Color::Red));
+
+        interpreter.state.curr_codel = Point(5, 5);
+        assert_eq!(interpreter.current_color(), None);
+        assert!(!interpreter.in_white_traversal());
+    }
+
+    #[test]
+    fn operations_which_underflow_leave_the_stack_alone() {
+        use Operation::*;
+
+        for op in [Add, Subtract, Multiply, Divide, Mod, Greater, Roll] {
+            let interpreter = execute_on(&[4], op);
+
+            interpreter.assert_stack(&[4]);
+            assert_eq!(interpreter.summary().underflows, 1, "{}", op);
+        }
+
+        for op in [Pop, Not, Pointer, Switch, Duplicate, OutNumber, OutChar] {
+            let interpreter = execute_on(&[], op);
+
+            interpreter.assert_stack(&[]);
+            assert_eq!(interpreter.summary().underflows, 1, "{}", op);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "stack mismatch from position 1")]
+    fn assert_stack_reports_where_the_stacks_differ() {
+        execute_on(&[1, 2, 3], Operation::Duplicate).assert_stack(&[1, 3, 3, 3]);
+    }
+
+    #[test]
+    fn pointer_turns_by_the_argument_modulo_4() {
+        use DirectionPointer::*;
+
+        for n in arguments() {
+            // negative arguments turn anticlockwise, e.g. -1 is three turns clockwise
+            let turns: BigInt = (&n % 4 + 4) % 4;
+            let expected = [Right, Down, Left, Up][turns.to_usize().unwrap()];
+
+            assert_eq!(execute_with(Operation::Pointer, &n).dp(), expected, "{}", n);
+        }
+    }
+
+    #[test]
+    fn switch_toggles_on_odd_arguments() {
+        for n in arguments() {
+            let expected = if (&n % 2u8).is_zero() {
+                CodelChooser::Left
+            } else {
+                CodelChooser::Right
+            };
+
+            assert_eq!(execute_with(Operation::Switch, &n).cc(), expected, "{}", n);
+        }
+    }
+
+    #[test]
+    fn steps_never_move_between_blocks_of_the_same_colour() {
+        for program in random_programs(200) {
+            let moves = Arc::new(Mutex::new(vec![]));
+            let recorded = Arc::clone(&moves);
+
+            let mut interpreter = Interpreter::builder(program)
+                .input(io::empty())
+                .prompt(false)
+                .output(io::sink())
+                .max_steps(500)
+                .trace_callback(move |event| {
+                    recorded
+                        .lock()
+                        .unwrap()
+                        .push((event.from_color, event.to_color))
+                })
+                .build();
+
+            // programs starting in black can't run
+            let _ = interpreter.run();
+
+            for &(from, to) in moves.lock().unwrap().iter() {
+                assert!(
+                    from != to || from.is_white(),
+                    "moved from {:?} to {:?}",
+                    from,
+                    to
+                );
+            }
+        }
+    }
+
+    /// An interpreter for `ascii`, with or without alternating restrictions in white
+    fn white_interpreter(ascii: &str, alternate: bool) -> InterpreterBuilder {
+        let program = Program::from_ascii(ascii).unwrap();
+
+        Interpreter::builder(program).alternate_white_restrictions(alternate)
+    }
+
+    #[test]
+    fn white_dead_ends_are_escaped_with_the_same_heading_either_way() {
+        // without alternating, the restriction at the end of the corridor toggles the CC and
+        // turns the DP down the way out, and when alternating the first toggles the CC and the
+        // second turns the DP, so both leave the same way
+        let program = "R..#\n##.#\n##G#\n";
+
+        for alternate in [false, true] {
+            let mut interpreter = white_interpreter(program, alternate).build();
+
+            assert!(matches!(interpreter.step(), Ok(StepOutcome::Moved { .. })));
+            assert_eq!(interpreter.current_codel(), Point(2, 2));
+            assert_eq!(interpreter.dp(), DirectionPointer::Down);
+            assert_eq!(interpreter.cc(), CodelChooser::Right);
+        }
+    }
+
+    #[test]
+    fn white_dead_ends_without_a_way_out_halt() {
+        // the slide turns down, left and up without reaching a colour, and never heads back
+        // left along the corridor, so it has to stop rather than loop
+        let program = "R..#\n##.#\n##.#\n####\n";
+
+        for alternate in [false, true] {
+            let mut interpreter = white_interpreter(program, alternate).build();
+            let outcome = interpreter.step();
+
+            assert!(
+                matches!(outcome, Ok(StepOutcome::Halted(HaltReason::WhiteLock))),
+                "alternate: {}",
+                alternate
+            );
+        }
+    }
+
+    #[test]
+    fn alternating_restrictions_visit_more_white_states() {
+        // without alternating, the slide visits the two corridor codels, turns down at the end
+        // and visits the codel below it, but alternating first only toggles the CC at the end,
+        // which visits one more state
+        let program = "R..#\n##.#\n##G#\n";
+
+        let mut interpreter = white_interpreter(program, false)
+            .max_white_states(4)
+            .build();
+        assert!(matches!(interpreter.step(), Ok(StepOutcome::Moved { .. })));
+        assert_eq!(interpreter.current_codel(), Point(2, 2));
+
+        let mut interpreter = white_interpreter(program, true).max_white_states(4).build();
+        assert!(matches!(
+            interpreter.step(),
+            Ok(StepOutcome::Halted(HaltReason::WhiteLock))
+        ));
+    }
+}
@@ -0,0 +1,269 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::RietError;
+
+/// An operation performed when the pointer moves between two coloured blocks.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Operation {
+    Noop,
+    Push,
+    Pop,
+
+    Add,
+    Subtract,
+    Multiply,
+
+    Divide,
+    Mod,
+    Not,
+
+    Greater,
+    Pointer,
+    Switch,
+
+    Duplicate,
+    Roll,
+    InNumber,
+
+    InChar,
+    OutNumber,
+    OutChar,
+}
+
+/// A mapping from hue change (outer index) and lightness change (inner index) to an operation
+pub type OperationTable = [[Operation; 3]; 6];
+
+/// The operation performed for each hue change (outer index) and lightness change (inner index).
+//
+// Hue change  None       1 Darker     2 Darker
+// None                   push         pop
+// 1 Step      add        subtract     multiply
+// 2 Steps     divide     mod          not
+// 3 Steps     greater    pointer      switch
+// 4 Steps     duplicate  roll         in(number)
+// 5 Steps     in(char)   out(number)  out(char)
+#[rustfmt::skip]
+pub const OPERATION_TABLE: OperationTable = {
+    use Operation::*;
+
+    [
+        [Noop,      Push,      Pop      ],
+        [Add,       Subtract,  Multiply ],
+        [Divide,    Mod,       Not      ],
+        [Greater,   Pointer,   Switch   ],
+        [Duplicate, Roll,      InNumber ],
+        [InChar,    OutNumber, OutChar  ],
+    ]
+};
+
+impl Operation {
+    /// Every operation, in the order they appear in the standard table
+    pub const ALL: [Operation; 18] = {
+        use Operation::*;
+
+        [
+            Noop, Push, Pop, Add, Subtract, Multiply, Divide, Mod, Not, Greater, Pointer, Switch,
+            Duplicate, Roll, InNumber, InChar, OutNumber, OutChar,
+        ]
+    };
+
+    /// The number of values the operation takes from the top of the stack
+    pub fn arity(self) -> usize {
+        use Operation::*;
+
+        match self {
+            Noop | Push | InNumber | InChar => 0,
+            Pop | Not | Pointer | Switch | Duplicate | OutNumber | OutChar => 1,
+            Add | Subtract | Multiply | Divide | Mod | Greater | Roll => 2,
+        }
+    }
+
+    /// Decode the operation for a given hue and lightness change
+    pub fn from_changes(hue_change: u32, lightness_change: u32) -> Option<Self> {
+        Self::from_table(&OPERATION_TABLE, hue_change, lightness_change)
+    }
+
+    /// Decode the operation for a given hue and lightness change using a custom table
+    pub fn from_table(
+        table: &OperationTable,
+        hue_change: u32,
+        lightness_change: u32,
+    ) -> Option<Self> {
+        table
+            .get(hue_change as usize)?
+            .get(lightness_change as usize)
+            .copied()
+    }
+}
+
+/// What happens when the pointer moves from one colour into another
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Transition {
+    /// No operation is executed, as white or black is involved or the colours are the same
+    NoOp,
+    /// The operation is executed
+    Op(Operation),
+}
+
+impl Transition {
+    /// The operation executed, if any
+    pub fn operation(self) -> Option<Operation> {
+        match self {
+            Transition::NoOp => None,
+            Transition::Op(op) => Some(op),
+        }
+    }
+}
+
+/// Parse an operation table for a piet dialect.
+///
+/// The table is written as six lines, one per hue change, each holding the names of the three
+/// operations for a lightness change of 0, 1 and 2, as they are shown in traces. Blank lines and
+/// anything following a `#` are ignored, so the default table is written as:
+///
+/// ```text
+/// # none      1 darker     2 darker
+/// noop        push         pop          # no hue change
+/// add         subtract     multiply     # 1 step
+/// divide      mod          not          # 2 steps
+/// greater     pointer      switch       # 3 steps
+/// duplicate   roll         in(number)   # 4 steps
+/// in(char)    out(number)  out(char)    # 5 steps
+/// ```
+pub fn parse_operation_table(s: &str) -> Result<OperationTable, RietError> {
+    let rows: Vec<Vec<&str>> = s
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default())
+        .map(|line| line.split_whitespace().collect::<Vec<_>>())
+        .filter(|ops| !ops.is_empty())
+        .collect();
+
+    if rows.len() != 6 {
+        return Err(RietError::InvalidOperationTable(format!(
+            "it needs a row for each of the 6 hue changes, found {} rows",
+            rows.len()
+        )));
+    }
+
+    let mut table = OPERATION_TABLE;
+
+    for (hue_change, row) in rows.into_iter().enumerate() {
+        if row.len() != 3 {
+            return Err(RietError::InvalidOperationTable(format!(
+                "row {} needs an operation for each of the 3 lightness changes, found {}",
+                hue_change,
+                row.len()
+            )));
+        }
+
+        for (lightness_change, name) in row.into_iter().enumerate() {
+            table[hue_change][lightness_change] = name.parse()?;
+        }
+    }
+
+    Ok(table)
+}
+
+/// Swap the columns of `table` for lightness changes of 1 and 2, for dialects which measure
+/// lightness changes towards lighter colours rather than darker ones.
+///
+/// Moving one step darker is then the same as two steps lighter, so e.g. `pop` is performed
+/// where the standard table performs `push`.
+pub fn invert_lightness(table: &OperationTable) -> OperationTable {
+    table.map(|[same, one, two]| [same, two, one])
+}
+
+impl FromStr for Operation {
+    type Err = RietError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|op| op.to_string() == s)
+            .ok_or_else(|| RietError::UnknownOperation(s.to_string()))
+    }
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use Operation::*;
+
+        #[rustfmt::skip]
+        let name = match self {
+            Noop      => "noop",
+            Push      => "push",
+            Pop       => "pop",
+
+            Add       => "add",
+            Subtract  => "subtract",
+            Multiply  => "multiply",
+
+            Divide    => "divide",
+            Mod       => "mod",
+            Not       => "not",
+
+            Greater   => "greater",
+            Pointer   => "pointer",
+            Switch    => "switch",
+
+            Duplicate => "duplicate",
+            Roll      => "roll",
+            InNumber  => "in(number)",
+
+            InChar    => "in(char)",
+            OutNumber => "out(number)",
+            OutChar   => "out(char)",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::program::Color;
+
+    #[test]
+    fn inverting_lightness_measures_changes_towards_lighter_colours() {
+        let inverted = invert_lightness(&OPERATION_TABLE);
+
+        // light to dark is one step lighter, so it does what light to normal usually does
+        assert_eq!(
+            Color::LightRed.transition_with(&Color::DarkRed, &inverted),
+            Color::LightRed.transition(&Color::Red)
+        );
+        assert_eq!(
+            Color::LightRed.transition_with(&Color::DarkRed, &inverted),
+            Transition::Op(Operation::Push)
+        );
+
+        // in general, moving to a colour does what moving the same number of steps the other
+        // way around the lightness cycle usually does, e.g. red to dark green does what red to
+        // light green does
+        for (i, from) in Color::ALL[..18].iter().enumerate() {
+            for (j, to) in Color::ALL[..18].iter().enumerate() {
+                let (from_lightness, to_lightness) = (i / 6, j / 6);
+                let mirrored_lightness = (2 * from_lightness + 3 - to_lightness) % 3;
+                let mirrored = Color::ALL[mirrored_lightness * 6 + j % 6];
+
+                assert_eq!(
+                    from.transition_with(to, &inverted),
+                    from.transition(&mirrored),
+                    "{:?} -> {:?}",
+                    from,
+                    to
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn inverting_lightness_twice_gives_the_same_table() {
+        assert_eq!(
+            invert_lightness(&invert_lightness(&OPERATION_TABLE)),
+            OPERATION_TABLE
+        );
+    }
+}
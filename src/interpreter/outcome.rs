@@ -0,0 +1,66 @@
+use super::Operation;
+
+use std::fmt;
+
+/// The reason the interpreter stopped executing a program
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum HaltReason {
+    /// The pointer could not leave a coloured block in any direction
+    Trapped,
+    /// The pointer slid around a white region without ever leaving it
+    WhiteLock,
+    /// The maximum number of steps was reached
+    MaxSteps,
+    /// The maximum number of operations was executed
+    MaxOps,
+    /// The time limit was reached
+    Timeout,
+    /// The stack underflowed more than the maximum number of times
+    UnderflowLimit,
+    /// The stack grew beyond its maximum size
+    StackLimit,
+    /// The program tried to write more than the maximum amount of output
+    OutputLimit,
+    /// The program tried to write a value while the stack was empty
+    EmptyOut,
+    /// The interpreter returned to a state it had already been in, so would loop forever
+    Cycle,
+}
+
+impl HaltReason {
+    /// Whether the program halted by itself as the spec describes, rather than being stopped
+    /// by one of the interpreter's limits or because it would loop forever
+    pub fn is_natural(self) -> bool {
+        matches!(self, HaltReason::Trapped | HaltReason::WhiteLock)
+    }
+}
+
+impl fmt::Display for HaltReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        #[rustfmt::skip]
+        let reason = match self {
+            HaltReason::Trapped        => "could not exit the current block",
+            HaltReason::WhiteLock      => "could not escape a white block",
+            HaltReason::MaxSteps       => "reached the maximum number of steps",
+            HaltReason::MaxOps         => "reached the maximum number of operations",
+            HaltReason::Timeout        => "reached the time limit",
+            HaltReason::UnderflowLimit => "the stack underflowed too many times",
+            HaltReason::StackLimit     => "the stack exceeded its maximum size",
+            HaltReason::OutputLimit    => "the output exceeded its maximum size",
+            HaltReason::EmptyOut       => "tried to output from an empty stack",
+            HaltReason::Cycle          => "detected an infinite loop",
+        };
+
+        write!(f, "{}", reason)
+    }
+}
+
+/// The result of performing a single step of a program
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StepOutcome {
+    /// The pointer moved into the next block, executing an operation unless it only moved
+    /// through or out of white
+    Moved { executed: Option<Operation> },
+    /// The program has stopped, further steps will halt for the same reason
+    Halted(HaltReason),
+}
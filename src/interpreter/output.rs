@@ -0,0 +1,46 @@
+use num_bigint::BigInt;
+
+/// A value written by the program, passed to the output callback
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OutputEvent {
+    /// A character written by `out(char)`
+    Char(char),
+    /// A number written by `out(number)`
+    Number(BigInt),
+}
+
+/// A callback which observes every value the program writes
+pub type OutputCallback = Box<dyn FnMut(OutputEvent) + Send>;
+
+#[cfg(test)]
+mod tests {
+    use crate::{Interpreter, Program, RietError};
+
+    use std::io::{self, Write};
+
+    /// An output which fails every write
+    struct BrokenPipe;
+
+    impl Write for BrokenPipe {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn failing_to_write_output_is_an_error() {
+        // push, out(number)
+        let program = Program::from_ascii("R1m\n").unwrap();
+        let mut interpreter = Interpreter::builder(program).output(BrokenPipe).build();
+
+        assert!(interpreter.step().is_ok());
+        assert!(matches!(
+            interpreter.step(),
+            Err(RietError::Io(e)) if e.kind() == io::ErrorKind::BrokenPipe
+        ));
+    }
+}
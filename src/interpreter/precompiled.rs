@@ -0,0 +1,299 @@
+use super::{Heading, Interpreter, Operation, StackBackend, StepOutcome};
+use crate::program::{CodelChooser, Color, DirectionPointer, Point};
+use crate::RietError;
+
+use std::collections::HashSet;
+
+/// The number of DP and CC combinations, which each have their own move out of a block
+const HEADINGS: usize = 8;
+
+/// The index of a colour block in the program
+type BlockId = usize;
+
+/// A move out of a coloured block which goes straight into the next block, without the
+/// pointer having to turn or slide through white
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(super) struct CompiledMove {
+    /// The block the move leaves
+    pub(super) from: BlockId,
+    /// The operation executed, if any
+    pub(super) op: Option<Operation>,
+    /// The block the move enters
+    pub(super) to: BlockId,
+    color: Color,
+    entry: Point,
+    entry_color: Color,
+}
+
+/// The move out of every coloured block for each DP and CC, worked out before the program runs.
+///
+/// Moves which need the pointer to turn, slide through white or halt depend on more than the
+/// block and heading the pointer starts with, so these aren't compiled and are taken by the
+/// general [`step`](Interpreter::step) instead.
+#[derive(Debug, Clone)]
+pub(super) struct Precompiled {
+    /// The move for each block and heading, at `block * HEADINGS + dp * 2 + cc`
+    moves: Vec<Option<CompiledMove>>,
+    /// The codel and block the last compiled move entered, so the next move can be found
+    /// without looking up the block of the codel the pointer is on
+    entered: Option<(Point, BlockId)>,
+}
+
+impl Precompiled {
+    pub(super) fn new<S: StackBackend>(interpreter: &Interpreter<S>) -> Self {
+        let program = &interpreter.program;
+        let mut moves = vec![None; program.num_blocks() * HEADINGS];
+        let mut seen = HashSet::new();
+
+        for row in 0..*program.rows() {
+            for col in 0..*program.cols() {
+                let curr = Point(row, col);
+                let color = *program.get_codel(row, col).unwrap();
+
+                let from = match program.block_id(&curr) {
+                    Some(id) if color.is_colored() && seen.insert(id) => id,
+                    _ => continue,
+                };
+
+                for dp in [
+                    DirectionPointer::Right,
+                    DirectionPointer::Down,
+                    DirectionPointer::Left,
+                    DirectionPointer::Up,
+                ] {
+                    for cc in [CodelChooser::Left, CodelChooser::Right] {
+                        let mut heading = Heading::new(dp, cc);
+                        let moved = interpreter.next_move(curr, color, &mut heading);
+
+                        let (entry, entry_color) = match moved {
+                            Ok((_, _, entry, entry_color))
+                                if heading.escapes == 0 && heading.white_entries == 0 =>
+                            {
+                                (entry, entry_color)
+                            }
+                            _ => continue,
+                        };
+
+                        let to = match program.block_id(&entry) {
+                            Some(to) => to,
+                            None => continue,
+                        };
+
+                        moves[slot(from, dp, cc)] = Some(CompiledMove {
+                            from,
+                            op: color
+                                .transition_with(&entry_color, &interpreter.operation_table)
+                                .operation(),
+                            to,
+                            color,
+                            entry,
+                            entry_color,
+                        });
+                    }
+                }
+            }
+        }
+
+        Self {
+            moves,
+            entered: None,
+        }
+    }
+
+    /// The compiled move out of `block` with the given DP and CC, if it has one
+    fn get(&self, block: BlockId, dp: DirectionPointer, cc: CodelChooser) -> Option<CompiledMove> {
+        self.moves.get(slot(block, dp, cc)).copied().flatten()
+    }
+
+    /// The number of moves which were compiled
+    pub(super) fn len(&self) -> usize {
+        self.moves.iter().flatten().count()
+    }
+}
+
+fn slot(block: BlockId, dp: DirectionPointer, cc: CodelChooser) -> usize {
+    block * HEADINGS + dp as usize * 2 + cc as usize
+}
+
+impl<S: StackBackend> Interpreter<S> {
+    /// Compile the moves out of the program's blocks if `precompile` is set
+    pub(super) fn precompile_moves(mut self, precompile: bool) -> Self {
+        if precompile {
+            self.precompiled = Some(Precompiled::new(&self));
+        }
+
+        self
+    }
+
+    /// The compiled move out of the current block with the current DP and CC, if moves were
+    /// precompiled and it has one
+    pub(super) fn compiled_move(&mut self) -> Option<CompiledMove> {
+        let precompiled = self.precompiled.as_mut()?;
+        let curr = self.state.curr_codel;
+
+        // the pointer may have been moved some other way since the last compiled move
+        let block = match precompiled.entered.take() {
+            Some((entry, block)) if entry == curr => block,
+            _ => self.program.block_id(&curr)?,
+        };
+
+        let compiled = precompiled.get(block, self.state.dp, self.state.cc)?;
+        debug_assert_eq!(compiled.from, block);
+
+        precompiled.entered = Some((compiled.entry, compiled.to));
+
+        Some(compiled)
+    }
+
+    /// Take a step with a compiled move, skipping the navigation and decoding of the operation
+    pub(super) fn take_compiled_step(
+        &mut self,
+        compiled: CompiledMove,
+    ) -> Result<StepOutcome, RietError> {
+        let exit = self.state.curr_codel;
+        self.slid_across_white = false;
+
+        self.trace_move(exit, compiled.color, compiled.entry, compiled.entry_color);
+
+        if let Some(op) = compiled.op {
+            self.execute(op)?;
+        }
+
+        Ok(self.finish_step(
+            exit,
+            compiled.color,
+            compiled.entry,
+            compiled.entry_color,
+            compiled.op,
+        ))
+    }
+
+    /// The number of moves out of blocks which were precompiled, if moves are precompiled
+    pub fn precompiled_moves(&self) -> Option<usize> {
+        self.precompiled.as_ref().map(Precompiled::len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::{HaltReason, MockClock, Summary};
+    use crate::program::tests::random_programs;
+    use crate::{Interpreter, Program, SharedBuffer};
+
+    use num_bigint::BigInt;
+    use std::io::Cursor;
+    use std::sync::{Arc, Mutex};
+
+    /// The parts of a trace event compared between runs
+    type Step = (
+        usize,
+        Point,
+        Color,
+        Point,
+        Color,
+        DirectionPointer,
+        CodelChooser,
+        Option<Operation>,
+        Vec<BigInt>,
+    );
+
+    /// Everything observable about a run of a program, which is run with a mock clock so the
+    /// elapsed time in the summary is the same for both runs
+    #[derive(Debug, PartialEq)]
+    struct Run {
+        steps: Vec<Step>,
+        reason: Result<HaltReason, String>,
+        summary: Summary,
+        output: Vec<u8>,
+    }
+
+    fn run(program: &Program, input: &str, precompile: bool) -> Run {
+        let steps = Arc::new(Mutex::new(Vec::new()));
+        let output = SharedBuffer::default();
+
+        let recorded = Arc::clone(&steps);
+        let mut interpreter = Interpreter::builder(program.clone())
+            .input(Cursor::new(input.as_bytes().to_vec()))
+            .prompt(false)
+            .output(output.clone())
+            .max_steps(2000)
+            .clock(MockClock::new())
+            .precompile_moves(precompile)
+            .trace_callback(move |event| {
+                recorded.lock().unwrap().push((
+                    event.step_no,
+                    event.from,
+                    event.from_color,
+                    event.to,
+                    event.to_color,
+                    event.dp,
+                    event.cc,
+                    event.executed,
+                    event.stack.to_vec(),
+                ))
+            })
+            .build();
+
+        assert_eq!(interpreter.precompiled_moves().is_some(), precompile);
+
+        let reason = interpreter.run().map_err(|e| e.to_string());
+        let summary = interpreter.summary();
+        drop(interpreter);
+
+        let steps = std::mem::take(&mut *steps.lock().unwrap());
+        let output = output.0.lock().unwrap().clone();
+
+        Run {
+            steps,
+            reason,
+            summary,
+            output,
+        }
+    }
+
+    fn assert_same_runs(program: &Program, input: &str) {
+        let interpreted = run(program, input, false);
+        let precompiled = run(program, input, true);
+
+        for (interpreted, precompiled) in interpreted.steps.iter().zip(&precompiled.steps) {
+            assert_eq!(interpreted, precompiled);
+        }
+
+        assert_eq!(interpreted, precompiled);
+    }
+
+    #[test]
+    fn straight_line_moves_are_compiled() {
+        let program = Program::from_ascii(&"R1".repeat(20)).unwrap();
+        let interpreter = Interpreter::builder(program).precompile_moves(true).build();
+
+        // every block can move left and right into its neighbours with either CC, apart from
+        // the two at the ends which only have a neighbour on one side
+        assert_eq!(interpreter.precompiled_moves(), Some(38 * 4 + 2 * 2));
+    }
+
+    #[test]
+    fn precompiled_runs_match_interpreted_runs() {
+        let programs = [
+            "R1".repeat(50),
+            "RG\n".to_string(),
+            "Rbc3y#\n".to_string(),
+            "RRb \nR#c \n.  3\ny#. \n".to_string(),
+            "R.1\n. #\nG.c\n".to_string(),
+            "R1RG\n#..b\nc3y#\n".to_string(),
+        ];
+
+        for program in programs {
+            let program = Program::from_ascii(&program).unwrap();
+            assert_same_runs(&program, "12 -7 ab 5\n");
+        }
+    }
+
+    #[test]
+    fn precompiled_runs_match_interpreted_runs_on_random_programs() {
+        for program in random_programs(200) {
+            assert_same_runs(&program, "3 x -12 y 40 z\n");
+        }
+    }
+}
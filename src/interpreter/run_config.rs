@@ -0,0 +1,17 @@
+use std::time::Duration;
+
+/// Limits for a single run of a program with [`Interpreter::run_with`].
+///
+/// These are checked in the same loop as the limits the interpreter was built with, so a run
+/// stops at whichever is reached first.
+///
+/// [`Interpreter::run_with`]: super::Interpreter::run_with
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct RunConfig {
+    /// Stop once the program has taken this many steps in total
+    pub max_steps: Option<usize>,
+    /// Stop once this run has taken longer than this
+    pub timeout: Option<Duration>,
+    /// Stop if the program returns to a state it was in earlier in this run
+    pub cycle_detection: bool,
+}
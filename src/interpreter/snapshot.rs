@@ -0,0 +1,14 @@
+use crate::program::{CodelChooser, DirectionPointer, Point};
+
+use num_bigint::BigInt;
+
+/// The state of the pointer and stack at some step, which a run can be resumed from with
+/// [`Interpreter::resume_at`](super::Interpreter::resume_at)
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Snapshot {
+    pub dp: DirectionPointer,
+    pub cc: CodelChooser,
+    pub curr_codel: Point,
+    /// The values on the stack, from bottom to top
+    pub stack: Vec<BigInt>,
+}
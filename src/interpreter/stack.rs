@@ -0,0 +1,144 @@
+use std::borrow::Cow;
+use std::hash::Hash;
+
+use num_bigint::BigInt;
+
+/// The storage behind the interpreter's stack.
+///
+/// The interpreter only relies on these methods, so the stack can be replaced with one which
+/// e.g. spills to disk or records every push and pop. The stack is hashed as part of the
+/// interpreter's state for cycle detection.
+pub trait StackBackend: Default + Hash {
+    fn push(&mut self, v: BigInt);
+
+    fn pop(&mut self) -> Option<BigInt>;
+
+    /// The value on the top of the stack
+    fn peek(&self) -> Option<&BigInt>;
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Rotate the top `depth` values so the value `mid` places above the lowest of them becomes
+    /// the lowest, as `slice::rotate_left` does.
+    ///
+    /// `depth` is at most the length of the stack and `mid` is less than `depth`. A roll by a
+    /// negative count rotates left by that count, and a roll by a positive count `n` buries the
+    /// top value, which is a rotation left by `depth - n`.
+    ///
+    /// Every roll rotates its whole depth, so this is on the hot path of programs which roll
+    /// deep stacks. The default pops and pushes each value, which the `roll` benchmark measures
+    /// as around 8 times slower than rotating them in place for a stack 100,000 values deep,
+    /// so backends which can should override it as the `Vec` one does.
+    fn rotate_left(&mut self, depth: usize, mid: usize) {
+        let mut section: Vec<BigInt> = (0..depth).filter_map(|_| self.pop()).collect();
+        section.reverse();
+        section.as_mut_slice().rotate_left(mid);
+
+        for v in section {
+            self.push(v);
+        }
+    }
+
+    /// The values on the stack, from bottom to top
+    fn values(&self) -> Cow<'_, [BigInt]>;
+}
+
+impl StackBackend for Vec<BigInt> {
+    fn push(&mut self, v: BigInt) {
+        Vec::push(self, v)
+    }
+
+    fn pop(&mut self) -> Option<BigInt> {
+        Vec::pop(self)
+    }
+
+    fn peek(&self) -> Option<&BigInt> {
+        self.last()
+    }
+
+    fn len(&self) -> usize {
+        Vec::len(self)
+    }
+
+    fn rotate_left(&mut self, depth: usize, mid: usize) {
+        let len = Vec::len(self);
+
+        self[len - depth..].rotate_left(mid);
+    }
+
+    fn values(&self) -> Cow<'_, [BigInt]> {
+        Cow::Borrowed(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{HaltReason, Interpreter, Program};
+
+    use std::cell::Cell;
+    use std::hash::Hasher;
+
+    /// A stack which counts how many times its values have been copied out
+    #[derive(Default)]
+    struct CopyCountingStack {
+        values: Vec<BigInt>,
+        copies: Cell<usize>,
+    }
+
+    impl Hash for CopyCountingStack {
+        fn hash<H: Hasher>(&self, state: &mut H) {
+            self.values.hash(state);
+        }
+    }
+
+    impl StackBackend for CopyCountingStack {
+        fn push(&mut self, v: BigInt) {
+            self.values.push(v);
+        }
+
+        fn pop(&mut self) -> Option<BigInt> {
+            self.values.pop()
+        }
+
+        fn peek(&self) -> Option<&BigInt> {
+            self.values.last()
+        }
+
+        fn len(&self) -> usize {
+            self.values.len()
+        }
+
+        fn values(&self) -> Cow<'_, [BigInt]> {
+            self.copies.set(self.copies.get() + 1);
+            Cow::Owned(self.values.clone())
+        }
+    }
+
+    #[test]
+    fn stack_is_not_copied_on_untraced_steps() {
+        let program = Program::from_ascii("RG\n").unwrap();
+        let mut interpreter = Interpreter::builder(program)
+            .max_steps(1000)
+            .build_with_stack(CopyCountingStack::default());
+
+        assert_eq!(interpreter.run().unwrap(), HaltReason::MaxSteps);
+        assert_eq!(interpreter.state.stack.copies.get(), 0);
+    }
+
+    #[test]
+    fn stack_is_copied_for_the_trace_callback() {
+        let program = Program::from_ascii("RG\n").unwrap();
+        let mut interpreter = Interpreter::builder(program)
+            .max_steps(10)
+            .trace_callback(|_| {})
+            .build_with_stack(CopyCountingStack::default());
+
+        assert_eq!(interpreter.run().unwrap(), HaltReason::MaxSteps);
+        assert_eq!(interpreter.state.stack.copies.get(), 10);
+    }
+}
@@ -0,0 +1,55 @@
+use std::fmt;
+use std::time::Duration;
+
+/// Statistics about a program's execution, useful for diagnosing hard to navigate programs
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct Summary {
+    /// The number of steps taken
+    pub steps: usize,
+    /// The number of operations executed, which leaves out steps which only moved the pointer
+    pub operations: usize,
+    /// The number of operations which failed as there weren't enough values on the stack
+    pub underflows: usize,
+    /// The number of times the CC was toggled or the DP rotated to escape a coloured block
+    pub escapes: usize,
+    /// The number of times the pointer entered a white region
+    pub white_entries: usize,
+    /// The deepest the stack has been, which is a guide to a suitable `--max-stack`
+    pub max_stack_depth: usize,
+    /// The time spent running the program, which is sampled every few steps so is approximate
+    /// until the program halts
+    pub elapsed: Duration,
+    /// The number and duration of the slowest step, if steps are being timed
+    pub slowest_step: Option<(usize, Duration)>,
+}
+
+impl Summary {
+    /// The number of steps taken per second, or `None` if no time has been measured yet
+    pub fn steps_per_second(&self) -> Option<f64> {
+        let secs = self.elapsed.as_secs_f64();
+
+        (secs > 0.0).then(|| self.steps as f64 / secs)
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "steps:         {}", self.steps)?;
+        writeln!(f, "operations:    {}", self.operations)?;
+        writeln!(f, "underflows:    {}", self.underflows)?;
+        writeln!(f, "escapes:       {}", self.escapes)?;
+        writeln!(f, "white entries: {}", self.white_entries)?;
+        writeln!(f, "max stack:     {}", self.max_stack_depth)?;
+        write!(f, "elapsed:       {:?}", self.elapsed)?;
+
+        if let Some(rate) = self.steps_per_second() {
+            write!(f, "\nsteps/second:  {:.0}", rate)?;
+        }
+
+        if let Some((step_no, duration)) = self.slowest_step {
+            write!(f, "\nslowest step:  {} ({:?})", step_no, duration)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,29 @@
+use super::Operation;
+use crate::program::{CodelChooser, Color, DirectionPointer, Point};
+
+use num_bigint::BigInt;
+
+/// A description of a single step of a program, passed to the trace callback.
+///
+/// This carries the same information as the `trace` level log messages, for embedders which
+/// want to observe execution without configuring a logger.
+#[derive(Debug, Clone)]
+pub struct TraceEvent<'a> {
+    /// The number of steps taken before this one
+    pub step_no: usize,
+    /// The codel the pointer left
+    pub from: Point,
+    pub from_color: Color,
+    /// The codel the pointer moved into
+    pub to: Point,
+    pub to_color: Color,
+    pub dp: DirectionPointer,
+    pub cc: CodelChooser,
+    /// The operation executed by the step, if any
+    pub executed: Option<Operation>,
+    /// The stack after the operation was executed, from bottom to top
+    pub stack: &'a [BigInt],
+}
+
+/// A callback which observes every step of a program
+pub type TraceCallback = Box<dyn FnMut(&TraceEvent) + Send>;
@@ -0,0 +1,129 @@
+#![feature(
+    derive_default_enum,
+    stmt_expr_attributes,
+)]
+
+use std::io::{self, Cursor, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+pub mod program;
+pub use program::Program;
+
+pub mod interpreter;
+
+#[cfg(feature = "bundle")]
+pub mod bundle;
+
+#[cfg(feature = "tui")]
+pub mod tui;
+
+mod error;
+pub use error::RietError;
+pub use interpreter::{HaltReason, Interpreter, InterpreterBuilder, Operation, StepOutcome};
+
+/// Run `program` for at most `max_steps` steps, reading from `input` instead of stdin.
+///
+/// Returns everything the program wrote to its output, with any invalid UTF-8 replaced, and
+/// the reason it halted.
+pub fn run_and_collect(
+    program: Program,
+    input: &[u8],
+    max_steps: usize,
+) -> Result<(String, HaltReason), RietError> {
+    let output = SharedBuffer::default();
+
+    let mut interpreter = Interpreter::builder(program)
+        .input(Cursor::new(input.to_vec()))
+        .prompt(false)
+        .output(output.clone())
+        .max_steps(max_steps)
+        .build();
+
+    let reason = interpreter.run()?;
+
+    let bytes = output.0.lock().unwrap();
+
+    Ok((String::from_utf8_lossy(&bytes).into_owned(), reason))
+}
+
+/// Load the program in the image at `path`, which has codels `codel_size` pixels wide, and run
+/// it for at most `max_steps` steps with `input` as its input.
+///
+/// Returns the program's output and the reason it halted, as [`run_and_collect`] does.
+pub fn interpret_image(
+    path: impl AsRef<Path>,
+    codel_size: u32,
+    input: &str,
+    max_steps: usize,
+) -> Result<(String, HaltReason), RietError> {
+    let program = Program::from_bytes(&std::fs::read(path)?, codel_size)?;
+
+    run_and_collect(program, input.as_bytes(), max_steps)
+}
+
+/// An output sink whose contents can still be read after it has been given to an interpreter
+#[derive(Debug, Default, Clone)]
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::program::Color;
+
+    /// A program which prints `text` and halts, as two rows of codels.
+    ///
+    /// Each character is pushed by leaving a block as wide as its code point into a block one
+    /// step darker, and printed by leaving that into a block 5 hues on and 2 steps darker. The
+    /// last block reaches down into the black second row, so every way out of it is blocked.
+    fn printing_program(text: &str) -> Program {
+        let color = |hue: usize, lightness: usize| Color::ALL[lightness % 3 * 6 + hue % 6];
+        let (mut hue, mut lightness) = (0, 0);
+        let mut row = vec![];
+
+        for c in text.chars() {
+            row.extend(std::iter::repeat_n(color(hue, lightness), c as usize));
+            lightness += 1;
+            row.push(color(hue, lightness));
+            hue += 5;
+            lightness += 2;
+        }
+
+        let last = color(hue, lightness);
+        row.extend([last, Color::Black]);
+
+        let cols = row.len();
+        let mut below = vec![Color::Black; cols];
+        below[cols - 3] = last;
+        below[cols - 2] = last;
+
+        row.extend(below);
+
+        Program::from_grid(2, cols as u32, row).unwrap()
+    }
+
+    #[test]
+    fn interpret_image_runs_hello_world() {
+        let path = std::env::temp_dir().join(format!("riet-hello-{}.png", std::process::id()));
+        let program = printing_program("Hello, World!");
+        program.save_codels(&path).unwrap();
+
+        let result = interpret_image(&path, 1, "", 1000);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            result.unwrap(),
+            ("Hello, World!".to_string(), HaltReason::Trapped)
+        );
+    }
+}
@@ -1,36 +1,29 @@
-#![feature(
-    derive_default_enum,
-    stmt_expr_attributes,
-    never_type,
-    array_windows,
-)]
-
+use anyhow::{bail, ensure, Context};
 use image::io::Reader as ImageReader;
-use std::path::PathBuf;
-use std::sync::Once;
+use image::{ImageFormat, Rgb, RgbImage};
+use num_bigint::BigInt;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Cursor, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use structopt::StructOpt;
 
-mod program;
-use program::Program;
-
-mod interpreter;
+use riet::interpreter::{
+    invert_lightness, parse_operation_table, CharEncoding, EmptyOut, InterpreterBuilder,
+    OperationTable, OutputEvent, RecordingReader, TraceEvent, DEFAULT_EMPTY_OUT_PLACEHOLDER,
+    OPERATION_TABLE,
+};
+use riet::program::{png_codel_size, unknown_colors, Color, Point, Sampler};
+use riet::{HaltReason, Interpreter, Operation, Program, RietError, StepOutcome};
 
-static mut MISSING_COLOR_WHITE: bool = true;
-static MISSING_COLOR_WHITE_INIT: Once = Once::new();
+/// Warn when the stack underflows more than this many times, as a program which does is
+/// almost certainly broken
+const UNDERFLOW_WARNING_THRESHOLD: usize = 1000;
 
-fn set_missing_color_white(v: bool) {
-    unsafe {
-        MISSING_COLOR_WHITE_INIT.call_once(|| {
-            MISSING_COLOR_WHITE = v;
-        })
-    }
-}
-
-pub fn missing_color_white() -> bool {
-    unsafe {
-        MISSING_COLOR_WHITE
-    }
-}
+/// Warn when more than this fraction of the colour blocks are thin or jagged, as the codel
+/// size is then probably wrong
+const JAGGED_BLOCK_WARNING_FRACTION: f64 = 0.25;
 
 #[derive(Debug, StructOpt)]
 #[structopt(
@@ -38,14 +31,31 @@ pub fn missing_color_white() -> bool {
     about = "Interprets programs written in the piet graphical programming language."
 )]
 struct Opt {
-    /// Set the codel size to use to interpret the program
+    /// Set the codel size to use to interpret the program, by default this is read from the
+    /// "piet:codel_size" text metadata of PNG images
     #[structopt(short, long)]
-    codel_size: u32,
+    codel_size: Option<u32>,
+
+    /// Set a fractional codel size, of at least 1, for images scaled by a non-integer factor.
+    /// Codel boundaries are rounded to the nearest pixel, so codels differ in width by a pixel
+    #[structopt(long, conflicts_with = "codel-size")]
+    codel_size_float: Option<f64>,
 
-    /// Enables trace log level
+    /// Enables trace log level, showing how each operation changes the stack. The operations
+    /// traced can be limited with a comma separated list in RIET_TRACE_OPS
     #[structopt(short, long)]
     trace: bool,
 
+    /// Trace the whole stack after every step instead of how it changed, the number of stack
+    /// entries shown can be set with --trace-stack-limit
+    #[structopt(long)]
+    trace_full_stack: bool,
+
+    /// Show at most this many entries from the top of the stack in traces, replacing the rest
+    /// with an ellipsis. Overrides RIET_TRACE_STACK_LIMIT [default: 32]
+    #[structopt(long)]
+    trace_stack_limit: Option<usize>,
+
     /// Enables info log level
     #[structopt(short, long)]
     info: bool,
@@ -54,20 +64,978 @@ struct Opt {
     #[structopt(long = "--missing-color-black")]
     missing_color_black: bool,
 
-    /// The maxiumum number of steps the interpreter will take
+    /// List the colours in the image which aren't part of the piet palette and exit
+    #[structopt(long)]
+    palette_report: bool,
+
+    /// Check the program for problems and exit without running it
+    #[structopt(long)]
+    check: bool,
+
+    /// The maxiumum number of steps the interpreter will take, where a step moves the pointer from one block to the next
     #[structopt(short, long)]
     max_steps: Option<usize>,
 
-    /// The name of the piet program to interpret
-    #[structopt(parse(from_os_str))]
-    file_name: PathBuf,
+    /// The maximum number of operations the interpreter will execute, which unlike --max-steps
+    /// doesn't count steps which only move the pointer
+    #[structopt(long)]
+    max_ops: Option<usize>,
+
+    /// Push these comma separated integers onto the stack, from bottom to top, before the
+    /// program starts
+    #[structopt(long, use_delimiter = true, allow_hyphen_values = true)]
+    seed_stack: Vec<BigInt>,
+
+    /// Halt the program once operations have failed due to a stack underflow more than this
+    /// many times
+    #[structopt(long)]
+    max_underflows: Option<usize>,
+
+    /// Halt the program once it tries to write more than this many bytes of output
+    #[structopt(long)]
+    max_output_bytes: Option<usize>,
+
+    /// What out(char) and out(number) do when the stack is empty: ignore writes nothing, error
+    /// halts the program and placeholder writes --empty-out-placeholder instead
+    #[structopt(long, default_value = "ignore")]
+    empty_out: EmptyOut,
+
+    /// The text written by out with an empty stack when --empty-out is placeholder
+    #[structopt(long, default_value = DEFAULT_EMPTY_OUT_PLACEHOLDER)]
+    empty_out_placeholder: String,
+
+    /// Write a newline when the program halts if its output doesn't already end in one, so
+    /// the shell prompt isn't left on the same line
+    #[structopt(long)]
+    trailing_newline: bool,
+
+    /// Save the program to this image file with exact piet colours and one pixel per codel
+    #[structopt(long, parse(from_os_str))]
+    save_canonical: Option<PathBuf>,
+
+    /// Save the graph of the program's colour blocks to this file in Graphviz's DOT language
+    #[structopt(long, parse(from_os_str))]
+    dot: Option<PathBuf>,
+
+    /// Save an SVG image of the program's colour blocks, labelled with their sizes, to this file
+    #[structopt(long, parse(from_os_str))]
+    svg: Option<PathBuf>,
+
+    /// Load the program from this file if it is newer than the image and was saved with the
+    /// same codel size, sampler and format, otherwise decode the image and save the program here
+    #[structopt(long, parse(from_os_str))]
+    cache: Option<PathBuf>,
+
+    /// Read the program's input from this file instead of stdin
+    #[structopt(long, parse(from_os_str))]
+    input: Option<PathBuf>,
+
+    /// Don't show a prompt before reading input, which is also left out when the input isn't
+    /// a terminal
+    #[structopt(long)]
+    no_prompt: bool,
+
+    /// Record the input read by the program to this file, so it can be replayed with --input
+    #[structopt(long, parse(from_os_str))]
+    record_input: Option<PathBuf>,
+
+    /// Write the output of out(number) to this file instead of stdout
+    #[structopt(long, parse(from_os_str))]
+    number_output: Option<PathBuf>,
+
+    /// Write the output of out(char) to this file instead of stdout
+    #[structopt(long, parse(from_os_str))]
+    char_output: Option<PathBuf>,
+
+    /// Skip out(number) with a warning when the number has more than this many digits
+    #[structopt(long)]
+    max_number_digits: Option<usize>,
+
+    /// How out(char) encodes values: utf8 writes unicode code points and skips invalid ones,
+    /// latin1 writes a single byte and skips values above 255, raw writes the lowest byte
+    #[structopt(long, default_value = "utf8")]
+    char_encoding: CharEncoding,
+
+    /// Print each character written by out(char) to stderr along with its code point, e.g.
+    /// 'A' U+0041, without changing the program's output
+    #[structopt(long)]
+    debug_output: bool,
+
+    /// Decode operations using the table in this file, for running piet dialects
+    #[structopt(long, parse(from_os_str))]
+    operation_table: Option<PathBuf>,
+
+    /// Measure lightness changes towards lighter colours rather than darker, as some piet
+    /// dialects do, so e.g. moving one step darker performs pop instead of push
+    #[structopt(long)]
+    invert_lightness: bool,
+
+    /// Print statistics about the program's execution to stderr once it halts
+    #[structopt(long)]
+    summary: bool,
+
+    /// Print the step which pushed each value on the stack to stderr once the program halts
+    #[structopt(long)]
+    track_provenance: bool,
+
+    /// Write the depth of the stack after every step to this file as CSV, to plot how the
+    /// stack grows over time
+    #[structopt(long, parse(from_os_str))]
+    stack_profile: Option<PathBuf>,
+
+    /// Time every step and include the slowest in the --summary, which slows the interpreter down
+    #[structopt(long)]
+    time_steps: bool,
+
+    /// Work out the move out of every block before running, so steps straight from one block
+    /// into the next are faster. Experimental, and requires riet to be built with the
+    /// precompile feature
+    #[structopt(long)]
+    precompile: bool,
+
+    /// Write the stack to this file as a JSON array of decimal strings once the program halts
+    #[structopt(long, parse(from_os_str))]
+    dump_stack: Option<PathBuf>,
+
+    /// Ask on stderr whether to run the program again each time it halts, reading the answer
+    /// from stdin
+    #[structopt(long = "loop")]
+    run_loop: bool,
+
+    /// Exit with an error if the program is stopped by a limit such as --max-steps, or loops
+    /// forever, rather than halting by itself
+    #[structopt(long)]
+    require_halt: bool,
+
+    /// Alternate between toggling the CC and rotating the DP at restrictions in white regions,
+    /// rather than doing both every time as the spec describes
+    #[structopt(long)]
+    alternate_white_restrictions: bool,
+
+    /// Describe each step to stderr before taking it: the colours it moves between and the
+    /// operation it will execute
+    #[structopt(long)]
+    explain: bool,
+
+    /// Step through the program in a terminal UI, taking a step on every key press. Input is
+    /// only read from --input or a bundle, and the output is printed once the UI is closed.
+    /// Requires riet to be built with the tui feature
+    #[structopt(long)]
+    tui: bool,
+
+    /// Halt when a slide across white passes through more than this many states [default: 1048576]
+    #[structopt(long)]
+    max_white_states: Option<usize>,
+
+    /// Write a line per step with its number, operation, DP|CC, stack depth and top of the stack
+    /// to this file, in a stable format which can be compared between runs
+    #[structopt(long, parse(from_os_str))]
+    golden_trace: Option<PathBuf>,
+
+    /// Decode the image as this format instead of guessing it: png, bmp, gif, webp or jpeg
+    #[structopt(long, parse(try_from_str = parse_image_format))]
+    format: Option<ImageFormat>,
+
+    /// How the colour of each codel is read from the image: majority takes the colour most of
+    /// its pixels have, center takes the colour of its centre pixel, which suits crisp images
+    #[structopt(long, default_value = "majority")]
+    sample: Sampler,
+
+    /// Read the program from a text file with a character per codel instead of an image: RYGCBM
+    /// for normal colours, rygcbm for light ones, 1 to 6 for dark ones, space or . for white
+    /// and # for black
+    #[structopt(long)]
+    ascii: bool,
+
+    /// Print the colour palette and the operation performed for each hue and lightness change,
+    /// using the table given by --operation-table and --invert-lightness, then exit
+    #[structopt(long)]
+    explain_colors: bool,
+
+    /// Load the program and its input from this zip archive, which holds them as program.png
+    /// and input.txt. Requires riet to be built with the bundle feature
+    #[structopt(long, parse(from_os_str), conflicts_with_all = &["file-name", "input"])]
+    bundle: Option<PathBuf>,
+
+    /// The name of the piet program to interpret, or a directory of programs to run one after
+    /// another with the same settings. Files written for options such as --dump-stack then
+    /// get each program's name added, e.g. stack.hello.json
+    #[structopt(parse(from_os_str), required_unless_one = &["bundle", "explain-colors"])]
+    file_name: Option<PathBuf>,
+}
+
+/// Print every colour in `img` outside the piet palette, with how often it appears
+fn palette_report(img: &RgbImage) {
+    let colors = unknown_colors(img);
+
+    if colors.is_empty() {
+        println!("Every pixel is a piet colour");
+        return;
+    }
+
+    println!("{:<9} {:>10}  first seen at (x, y)", "colour", "pixels");
+
+    for color in colors {
+        let Rgb([r, g, b]) = color.rgb;
+
+        println!(
+            "#{:02x}{:02x}{:02x}   {:>10}  {:?}",
+            r, g, b, color.count, color.sample
+        );
+    }
+}
+
+/// The colour as a hex triplet, e.g. #ff0000
+fn hex_color(color: Color) -> String {
+    let Rgb([r, g, b]) = color.to_rgb8();
+
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+/// Print a reference of the colours and the operations they perform when moving between them
+fn explain_colors(table: &OperationTable) {
+    let number = |n: Option<u32>| n.map_or_else(|| "-".to_string(), |n| n.to_string());
+
+    println!("{:<14} {:<8} {:<4} lightness", "colour", "rgb", "hue");
+
+    for color in Color::ALL {
+        println!(
+            "{:<14} {:<8} {:<4} {}",
+            color.name(),
+            hex_color(color),
+            number(color.hue_number()),
+            number(color.lightness_number())
+        );
+    }
+
+    // the coloured colours go light to dark, in hue order within each lightness
+    let hues: String = Color::ALL[6..12]
+        .iter()
+        .map(|hue| format!("{:<9}", hue.name()))
+        .collect();
+    println!("\n{:<8}{}", "", hues.trim_end());
+
+    for (lightness, colors) in ["light", "normal", "dark"]
+        .iter()
+        .zip(Color::ALL[..18].chunks(6))
+    {
+        let hexes: Vec<_> = colors.iter().map(|&color| hex_color(color)).collect();
+
+        println!("{:<8}{}", lightness, hexes.join("  "));
+    }
+
+    println!(
+        "\n{:<10} {:<12} {:<12} 2 darker",
+        "hue change", "none", "1 darker"
+    );
+
+    for (hue_change, ops) in table.iter().enumerate() {
+        let [none, one, two] = ops.map(|op| op.to_string());
+
+        println!("{:<10} {:<12} {:<12} {}", hue_change, none, one, two);
+    }
+}
+
+/// Parse the name of an image format which can be given with --format
+fn parse_image_format(s: &str) -> anyhow::Result<ImageFormat> {
+    match s {
+        "png" => Ok(ImageFormat::Png),
+        "bmp" => Ok(ImageFormat::Bmp),
+        "gif" => Ok(ImageFormat::Gif),
+        "webp" => Ok(ImageFormat::WebP),
+        "jpeg" | "jpg" => Ok(ImageFormat::Jpeg),
+        _ => bail!(
+            "Unsupported image format {:?}, expected png, bmp, gif, webp or jpeg",
+            s
+        ),
+    }
+}
+
+/// The file the program is loaded from, either its image or the bundle holding it
+fn program_path(opt: &Opt) -> &Path {
+    opt.bundle
+        .as_deref()
+        .or(opt.file_name.as_deref())
+        .expect("Either a program or a bundle is required")
+}
+
+/// Read the encoded image of the program and, if it is bundled with one, its input
+fn read_program(opt: &Opt) -> anyhow::Result<(Vec<u8>, Option<Vec<u8>>)> {
+    let path = program_path(opt);
+
+    if opt.bundle.is_some() {
+        let (program, input) = open_bundle(path)?;
+        Ok((program, Some(input)))
+    } else {
+        Ok((fs::read(path)?, None))
+    }
+}
+
+#[cfg(feature = "bundle")]
+fn open_bundle(path: &Path) -> anyhow::Result<(Vec<u8>, Vec<u8>)> {
+    let bundle = riet::bundle::Bundle::open(path)?;
+
+    Ok((bundle.program, bundle.input))
+}
+
+#[cfg(not(feature = "bundle"))]
+fn open_bundle(_path: &Path) -> anyhow::Result<(Vec<u8>, Vec<u8>)> {
+    bail!("--bundle requires riet to be built with the bundle feature")
+}
+
+/// Decode the program's image, as the format given with --format if there is one
+fn open_image(opt: &Opt, image: &[u8]) -> anyhow::Result<RgbImage> {
+    let mut reader = ImageReader::new(Cursor::new(image));
+
+    match opt.format {
+        Some(format) => reader.set_format(format),
+        None => reader = reader.with_guessed_format()?,
+    }
+
+    Ok(reader.decode()?.to_rgb8())
+}
+
+/// The colour pixels outside the piet palette are read as
+fn missing_color(opt: &Opt) -> Color {
+    if opt.missing_color_black {
+        Color::Black
+    } else {
+        Color::White
+    }
+}
+
+/// Decode the program from its image
+fn decode_program(opt: &Opt, image: &[u8]) -> anyhow::Result<Program> {
+    if opt.ascii {
+        let text = std::str::from_utf8(image).context("The program is not valid UTF-8 text")?;
+
+        return Ok(Program::from_ascii(text)?);
+    }
+
+    if let Some(codel_size) = opt.codel_size_float {
+        let img = open_image(opt, image)?;
+        let program =
+            Program::new_from_imagebuffer_scaled(&img, codel_size, opt.sample, missing_color(opt))?;
+
+        log_loaded(&program, codel_size);
+
+        return Ok(program);
+    }
+
+    let codel_size = match opt.codel_size {
+        Some(codel_size) => codel_size,
+        None => png_codel_size(image)?.context(
+            "No codel size was given with --codel-size and none was found in the image's metadata",
+        )?,
+    };
+
+    let img = open_image(opt, image)?;
+
+    let program = Program::new_from_imagebuffer(&img, codel_size, opt.sample, missing_color(opt))?;
+
+    log_loaded(&program, codel_size);
+
+    Ok(program)
+}
+
+/// The operation table given by --operation-table and --invert-lightness
+fn operation_table(opt: &Opt) -> anyhow::Result<OperationTable> {
+    let table = match &opt.operation_table {
+        Some(path) => parse_operation_table(&fs::read_to_string(path)?)?,
+        None => OPERATION_TABLE,
+    };
+
+    Ok(if opt.invert_lightness {
+        invert_lightness(&table)
+    } else {
+        table
+    })
+}
+
+/// Apply the limits and the settings which change how the program runs from the command line
+fn configure_limits(mut builder: InterpreterBuilder, opt: &Opt) -> InterpreterBuilder {
+    if let Some(max_steps) = opt.max_steps {
+        builder = builder.max_steps(max_steps);
+    }
+
+    if let Some(max_ops) = opt.max_ops {
+        builder = builder.max_ops(max_ops);
+    }
+
+    if let Some(max_underflows) = opt.max_underflows {
+        builder = builder.max_underflows(max_underflows);
+    }
+
+    if let Some(max_bytes) = opt.max_output_bytes {
+        builder = builder.max_output_bytes(max_bytes);
+    }
+
+    if let Some(max_states) = opt.max_white_states {
+        builder = builder.max_white_states(max_states);
+    }
+
+    builder = builder.alternate_white_restrictions(opt.alternate_white_restrictions);
+
+    if let Some(max_digits) = opt.max_number_digits {
+        builder = builder.max_number_digits(max_digits);
+    }
+
+    builder = builder.char_encoding(opt.char_encoding);
+    builder = builder.empty_out(opt.empty_out);
+    builder = builder.empty_out_placeholder(opt.empty_out_placeholder.clone());
+
+    builder
+}
+
+#[cfg(feature = "precompile")]
+fn precompile_moves(
+    builder: InterpreterBuilder,
+    precompile: bool,
+) -> anyhow::Result<InterpreterBuilder> {
+    Ok(builder.precompile_moves(precompile))
+}
+
+#[cfg(not(feature = "precompile"))]
+fn precompile_moves(
+    builder: InterpreterBuilder,
+    precompile: bool,
+) -> anyhow::Result<InterpreterBuilder> {
+    ensure!(
+        !precompile,
+        "--precompile requires riet to be built with the precompile feature"
+    );
+
+    Ok(builder)
+}
+
+/// Apply everything from the command line which changes how the program runs, apart from
+/// where its input and output go
+fn configure_interpreter(
+    mut builder: InterpreterBuilder,
+    opt: &Opt,
+) -> anyhow::Result<InterpreterBuilder> {
+    builder = builder.trace_full_stack(opt.trace_full_stack);
+
+    if let Some(limit) = opt.trace_stack_limit {
+        builder = builder.trace_stack_limit(limit);
+    } else if let Ok(limit) = env::var("RIET_TRACE_STACK_LIMIT") {
+        builder = builder.trace_stack_limit(limit.parse()?);
+    }
+
+    if let Ok(ops) = env::var("RIET_TRACE_OPS") {
+        let ops = ops
+            .split(',')
+            .map(|op| op.trim().parse())
+            .collect::<Result<Vec<Operation>, _>>()?;
+
+        builder = builder.trace_operations(ops);
+    }
+
+    builder = configure_limits(builder, opt);
+    builder = builder.track_provenance(opt.track_provenance);
+    builder = builder.trailing_newline(opt.trailing_newline);
+    builder = builder.record_stack_depths(opt.stack_profile.is_some());
+    builder = builder.time_steps(opt.time_steps);
+    builder = precompile_moves(builder, opt.precompile)?;
+    builder = builder.operation_table(operation_table(opt)?);
+
+    Ok(builder)
+}
+
+/// The golden trace being written for a run
+type GoldenTrace = Arc<Mutex<BufWriter<File>>>;
+
+/// Send the program's output to the files given by --number-output and --char-output, and
+/// write its steps to --golden-trace.
+///
+/// `program` is the program's path when a directory of programs is being run, see
+/// [`output_path`].
+fn configure_outputs(
+    mut builder: InterpreterBuilder,
+    opt: &Opt,
+    program: Option<&Path>,
+) -> anyhow::Result<(InterpreterBuilder, Option<GoldenTrace>)> {
+    if let Some(path) = &opt.number_output {
+        builder = builder.number_output(File::create(output_path(path, program))?);
+    }
+
+    if let Some(path) = &opt.char_output {
+        builder = builder.char_output(File::create(output_path(path, program))?);
+    }
+
+    let golden_trace = match &opt.golden_trace {
+        Some(path) => {
+            let file = File::create(output_path(path, program))?;
+            Some(Arc::new(Mutex::new(BufWriter::new(file))))
+        }
+        None => None,
+    };
+
+    if let Some(out) = &golden_trace {
+        let out = out.clone();
+
+        builder = builder.trace_callback(move |event| {
+            write_golden_step(&mut *out.lock().unwrap(), event)
+                .expect("Failed to write golden trace")
+        });
+    }
+
+    Ok((builder, golden_trace))
+}
+
+/// Report on a run once the program halts with `reason`, as asked for on the command line.
+///
+/// Fails if --require-halt was given and the program didn't halt by itself.
+fn report_halt(
+    opt: &Opt,
+    interpreter: &Interpreter,
+    reason: HaltReason,
+    golden_trace: Option<&GoldenTrace>,
+    program: Option<&Path>,
+) -> anyhow::Result<()> {
+    let summary = interpreter.summary();
+
+    if summary.underflows > UNDERFLOW_WARNING_THRESHOLD {
+        log::warn!(
+            "The stack underflowed {} times, the program is likely broken or misnavigating",
+            summary.underflows
+        );
+    }
+
+    if opt.summary {
+        eprintln!("halted:        {}", reason);
+        eprintln!("{}", summary);
+    }
+
+    if let Some(out) = golden_trace {
+        let mut out = out.lock().unwrap();
+        writeln!(out, "halted {:?}", reason)?;
+        out.flush()?;
+    }
+
+    if let Some(provenance) = interpreter.provenance() {
+        print_provenance(interpreter.stack(), provenance);
+    }
+
+    if let (Some(path), Some(depths)) = (&opt.stack_profile, interpreter.stack_depths()) {
+        let file = File::create(output_path(path, program))?;
+        write_stack_profile(depths, BufWriter::new(file))?;
+    }
+
+    if let Some(path) = &opt.dump_stack {
+        let file = File::create(output_path(path, program))?;
+        dump_stack(interpreter.stack(), file)?;
+    }
+
+    ensure!(
+        !opt.require_halt || reason.is_natural(),
+        "The program didn't halt by itself: {}",
+        reason
+    );
+
+    Ok(())
+}
+
+/// The file written for an option given `path` when running the program at `program`.
+///
+/// When a directory of programs is run, the program's name is added to the file name so each
+/// program writes its own, e.g. stack.hello.json for stack.json and hello.png.
+fn output_path(path: &Path, program: Option<&Path>) -> PathBuf {
+    let program = match program.and_then(Path::file_stem) {
+        Some(program) => program,
+        None => return path.to_path_buf(),
+    };
+
+    let mut name = path.file_stem().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(program);
+
+    if let Some(extension) = path.extension() {
+        name.push(".");
+        name.push(extension);
+    }
+
+    path.with_file_name(name)
+}
+
+/// Print a character written by out(char) to stderr with its code point, for --debug-output
+fn debug_output(event: &OutputEvent) {
+    if let OutputEvent::Char(c) = event {
+        eprintln!("out(char) {:?} U+{:04X}", c, *c as u32);
+    }
+}
+
+/// Collect the program's output in a string rather than writing it to stdout, also printing
+/// each character to stderr if `debug` is set
+fn collect_output(
+    builder: InterpreterBuilder,
+    debug: bool,
+) -> (InterpreterBuilder, Arc<Mutex<String>>) {
+    let output = Arc::new(Mutex::new(String::new()));
+    let collected = output.clone();
+
+    let builder = builder.output(io::sink()).output_callback(move |event| {
+        if debug {
+            debug_output(&event);
+        }
+
+        let mut output = collected.lock().unwrap();
+
+        match event {
+            OutputEvent::Char(c) => output.push(c),
+            OutputEvent::Number(n) => output.push_str(&n.to_string()),
+        }
+    });
+
+    (builder, output)
+}
+
+/// Run every program in `dir` with the same settings and print a line with the result of each,
+/// or with --check, check each of them instead.
+///
+/// Each program reads its input from --input, or has no input, and its output is shown in its
+/// result rather than written to stdout. Files which can't be decoded are skipped, and
+/// programs which fail are reported without stopping the rest from running.
+fn run_directory(opt: &Opt, dir: &Path) -> anyhow::Result<()> {
+    let single_program_flags = [
+        ("--tui", opt.tui),
+        ("--loop", opt.run_loop),
+        ("--cache", opt.cache.is_some()),
+        ("--dot", opt.dot.is_some()),
+        ("--svg", opt.svg.is_some()),
+        ("--save-canonical", opt.save_canonical.is_some()),
+        ("--palette-report", opt.palette_report),
+    ];
+
+    if let Some((flag, _)) = single_program_flags.iter().find(|(_, given)| *given) {
+        bail!("{} can't be used with a directory of programs", flag);
+    }
+
+    let table = operation_table(opt)?;
+
+    let mut paths = fs::read_dir(dir)?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    paths.sort();
+
+    let mut failed = 0;
+
+    for path in paths.into_iter().filter(|path| path.is_file()) {
+        let program = match decode_program(opt, &fs::read(&path)?) {
+            Ok(program) => program,
+            Err(e) => {
+                log::warn!("Skipping {}: {}", path.display(), e);
+                continue;
+            }
+        };
+
+        if opt.check {
+            match check(&program, &table) {
+                Ok(unreachable) if unreachable.is_empty() => {
+                    println!("{}: passed all checks", path.display())
+                }
+                Ok(unreachable) => println!(
+                    "{}: passed all checks, but can never execute {}",
+                    path.display(),
+                    unreachable.join(", ")
+                ),
+                Err(e) => {
+                    failed += 1;
+                    println!("{}: error: {:#}", path.display(), e);
+                }
+            }
+
+            continue;
+        }
+
+        match run_directory_program(opt, program, &path) {
+            Ok((reason, steps, output)) => println!(
+                "{}: {} after {} steps, output {:?}",
+                path.display(),
+                reason,
+                steps,
+                output
+            ),
+            Err(e) => {
+                failed += 1;
+                println!("{}: error: {:#}", path.display(), e);
+            }
+        }
+    }
+
+    ensure!(failed == 0, "{} of the programs failed", failed);
+
+    Ok(())
+}
+
+/// Run a program from a directory with the settings from the command line, returning the
+/// reason it halted, the number of steps it took and its output
+fn run_directory_program(
+    opt: &Opt,
+    program: Program,
+    path: &Path,
+) -> anyhow::Result<(HaltReason, usize, String)> {
+    let input: Box<dyn BufRead + Send> = match &opt.input {
+        Some(input) => Box::new(BufReader::new(File::open(input)?)),
+        None => Box::new(io::empty()),
+    };
+
+    let builder = configure_interpreter(Interpreter::builder(program), opt)?.prompt(false);
+
+    let builder = match &opt.record_input {
+        Some(record) => {
+            let file = File::create(output_path(record, Some(path)))?;
+            builder.input(RecordingReader::new(input, file))
+        }
+        None => builder.input(input),
+    };
+
+    let (builder, output) = collect_output(builder, opt.debug_output);
+    let (builder, golden_trace) = configure_outputs(builder, opt, Some(path))?;
+    let mut interpreter = builder.build();
+
+    if !opt.seed_stack.is_empty() {
+        interpreter = interpreter.with_initial_stack(opt.seed_stack.clone());
+    }
+
+    let reason = if opt.explain {
+        explain(&mut interpreter, opt.invert_lightness)?
+    } else {
+        interpreter.run()?
+    };
+
+    report_halt(opt, &interpreter, reason, golden_trace.as_ref(), Some(path))?;
+
+    let output = output.lock().unwrap().clone();
+
+    Ok((reason, interpreter.summary().steps, output))
+}
+
+/// Confirm the program decoded as expected before it starts running
+fn log_loaded(program: &Program, codel_size: impl std::fmt::Display) {
+    log::info!(
+        "loaded {}x{} program, codel size {}, {} blocks",
+        program.cols(),
+        program.rows(),
+        codel_size,
+        program.num_blocks()
+    );
+}
+
+/// Whether `path` exists and was modified after `than`
+fn is_newer(path: &Path, than: &Path) -> bool {
+    let modified = |path: &Path| fs::metadata(path).and_then(|m| m.modified()).ok();
+
+    matches!((modified(path), modified(than)), (Some(a), Some(b)) if a > b)
+}
+
+/// The settings which change how the program is decoded from its image, which a cached
+/// program must have been built with to be used
+fn cache_settings(opt: &Opt) -> String {
+    format!(
+        "codel size {:?}, fractional codel size {:?}, sampler {}, format {:?}, ascii {}, \
+         missing colours black {}",
+        opt.codel_size,
+        opt.codel_size_float,
+        opt.sample,
+        opt.format,
+        opt.ascii,
+        opt.missing_color_black
+    )
+}
+
+/// Load the program from `cache` if it is newer than the image and was built with the same
+/// settings, otherwise decode the image and save the program to `cache`
+fn load_cached_program(opt: &Opt, cache: &Path, image: &[u8]) -> anyhow::Result<Program> {
+    let settings = cache_settings(opt);
+
+    if is_newer(cache, program_path(opt)) {
+        let cached = File::open(cache)
+            .map_err(RietError::from)
+            .and_then(|file| Program::deserialize_from(BufReader::new(file), &settings));
+
+        match cached {
+            Ok(program) => {
+                log::info!("Loaded the program from the cache at {:?}", cache);
+                return Ok(program);
+            }
+            Err(e) => log::info!("Rebuilding the cache at {:?}: {}", cache, e),
+        }
+    }
+
+    let program = decode_program(opt, image)?;
+    program.serialize_to(BufWriter::new(File::create(cache)?), &settings)?;
+
+    Ok(program)
+}
+
+/// Statically check a program for problems which would stop it from running correctly, looking
+/// up the operations it can execute in `table`.
+///
+/// Returns the operations the program can never execute, which doesn't stop it from running.
+fn check(program: &Program, table: &OperationTable) -> anyhow::Result<Vec<String>> {
+    ensure!(
+        program.has_colored_codels(),
+        "The program contains no colored codels so it can never execute an operation"
+    );
+
+    ensure!(
+        matches!(program.get_codel(0, 0), Some(color) if !color.is_black()),
+        "The program starts in a black codel so it can't be run"
+    );
+
+    ensure!(
+        program.block_can_be_left(&Point(0, 0)),
+        "The pointer can never leave the block the program starts in, so it will halt immediately"
+    );
+
+    let reachable = program.reachable_operations_with(table);
+    let unreachable: Vec<String> = Operation::ALL
+        .into_iter()
+        .filter(|op| *op != Operation::Noop && !reachable.contains(op))
+        .map(|op| op.to_string())
+        .collect();
+
+    let jagged = program.jagged_block_fraction();
+    if jagged > JAGGED_BLOCK_WARNING_FRACTION {
+        log::warn!(
+            "{:.0}% of the colour blocks are thin or jagged, the codel size may be wrong",
+            jagged * 100.0
+        );
+    }
+
+    log::info!("The program passed all checks");
+
+    Ok(unreachable)
+}
+
+/// Write a step to a golden trace as `<step> <operation> <dp>|<cc> <depth> <top>`, using `-`
+/// when no operation is executed or the stack is empty
+fn write_golden_step(out: &mut impl Write, event: &TraceEvent) -> io::Result<()> {
+    let op = event.executed.map(|op| op.to_string());
+    let top = event.stack.last().map(|v| v.to_string());
+
+    writeln!(
+        out,
+        "{} {} {:?}|{:?} {} {}",
+        event.step_no,
+        op.as_deref().unwrap_or("-"),
+        event.dp,
+        event.cc,
+        event.stack.len(),
+        top.as_deref().unwrap_or("-")
+    )
+}
+
+/// Write the depth of the stack after each step as CSV with a header
+fn write_stack_profile(depths: &[usize], mut out: impl Write) -> io::Result<()> {
+    writeln!(out, "step,depth")?;
+
+    for (step, depth) in depths.iter().enumerate() {
+        writeln!(out, "{},{}", step, depth)?;
+    }
+
+    out.flush()
+}
+
+/// Write `stack` as a JSON array of decimal strings, so no precision is lost for large numbers
+fn dump_stack(stack: &[BigInt], mut out: impl Write) -> io::Result<()> {
+    write!(out, "[")?;
+
+    for (i, v) in stack.iter().enumerate() {
+        if i != 0 {
+            write!(out, ", ")?;
+        }
+
+        write!(out, "\"{}\"", v)?;
+    }
+
+    writeln!(out, "]")
+}
+
+/// Print each value on the stack with the step which pushed it, from the top of the stack down
+fn print_provenance(stack: &[BigInt], provenance: &[usize]) {
+    eprintln!("stack provenance, top first:");
+
+    for (v, step) in stack.iter().zip(provenance).rev() {
+        eprintln!("  {:>10}  pushed by step {}", v, step);
+    }
+}
+
+/// Step through the program in the terminal UI, then print its output
+#[cfg(feature = "tui")]
+fn run_tui(builder: InterpreterBuilder, seed_stack: Vec<BigInt>) -> anyhow::Result<()> {
+    // writing the output while the UI is drawn would garble it
+    let (builder, output) = collect_output(builder, false);
+    let mut interpreter = builder.build().with_initial_stack(seed_stack);
+
+    let halted = riet::tui::run(&mut interpreter)?;
+
+    print!("{}", output.lock().unwrap());
+    io::stdout().flush()?;
+
+    if let Some(reason) = halted {
+        log::info!("halted: {}", reason);
+    }
+
+    Ok(())
+}
+
+#[cfg(not(feature = "tui"))]
+fn run_tui(_builder: InterpreterBuilder, _seed_stack: Vec<BigInt>) -> anyhow::Result<()> {
+    bail!("--tui requires riet to be built with the tui feature")
+}
+
+/// Run the program, describing each step before it is taken.
+///
+/// The hue and lightness change are only shown for moves directly between coloured blocks, as
+/// sliding across white never executes an operation. With --invert-lightness the lightness
+/// change is measured towards lighter colours, to match the table the operation comes from.
+fn explain(interpreter: &mut Interpreter, invert_lightness: bool) -> anyhow::Result<HaltReason> {
+    loop {
+        let curr_color = interpreter.current_color();
+        let next_move = interpreter.peek_next_move();
+        let next_op = interpreter.peek_next_operation();
+
+        if let (Some(curr_color), Some((exit_color, next, next_color)), Some((_, op))) =
+            (curr_color, next_move, next_op)
+        {
+            let hue_change = exit_color.hue_change(&next_color);
+            let lightness_change = exit_color.lightness_change(&next_color);
+
+            let change = match hue_change.zip(lightness_change) {
+                Some((hue, lightness)) => {
+                    let lightness = if invert_lightness {
+                        (3 - lightness) % 3
+                    } else {
+                        lightness
+                    };
+
+                    format!("hue change {}, lightness change {}", hue, lightness)
+                }
+                None => "across white".to_string(),
+            };
+
+            eprintln!(
+                "{:?} {:?} -> {:?} {:?} ({}): {}",
+                interpreter.current_codel(),
+                curr_color,
+                next,
+                next_color,
+                change,
+                op,
+            );
+        }
+
+        if let StepOutcome::Halted(reason) = interpreter.step()? {
+            return Ok(reason);
+        }
+    }
 }
 
 fn main() -> anyhow::Result<()> {
     let opt = Opt::from_args();
 
-    set_missing_color_white(!opt.missing_color_black);
-
     let log_level = if opt.trace {
         log::Level::Trace
     } else if opt.info {
@@ -78,17 +1046,126 @@ fn main() -> anyhow::Result<()> {
 
     simple_logger::init_with_level(log_level)?;
 
-    let img = ImageReader::open(opt.file_name)?.decode()?;
+    if opt.explain_colors {
+        explain_colors(&operation_table(&opt)?);
+        return Ok(());
+    }
 
-    let program = Program::new_from_imagebuffer(&img.to_rgb8(), opt.codel_size);
+    if let Some(dir) = opt.file_name.as_deref().filter(|path| path.is_dir()) {
+        return run_directory(&opt, dir);
+    }
 
-    let mut interpreter = program.into_interpreter();
+    let (image, bundled_input) = read_program(&opt)?;
 
-    if let Some(max_steps) = opt.max_steps {
-        interpreter.run_until(max_steps)?;
+    if opt.palette_report {
+        let img = open_image(&opt, &image)?;
+        palette_report(&img);
+        return Ok(());
+    }
 
-        Ok(())
-    } else {
-        interpreter.run()?;
+    let program = match &opt.cache {
+        Some(cache) => load_cached_program(&opt, cache, &image)?,
+        None => decode_program(&opt, &image)?,
+    };
+
+    if let Some(path) = &opt.save_canonical {
+        program.save_codels(path)?;
+    }
+
+    if let Some(path) = &opt.dot {
+        fs::write(path, program.to_dot())?;
+    }
+
+    if let Some(path) = &opt.svg {
+        fs::write(path, program.to_svg())?;
+    }
+
+    if opt.check {
+        let unreachable = check(&program, &operation_table(&opt)?)?;
+
+        if !unreachable.is_empty() {
+            println!(
+                "operations the program can never execute: {}",
+                unreachable.join(", ")
+            );
+        }
+
+        return Ok(());
+    }
+
+    let mut builder = configure_interpreter(Interpreter::builder(program), &opt)?;
+
+    let reads_stdin = opt.input.is_none() && bundled_input.is_none();
+    let interactive = reads_stdin && io::stdin().is_terminal();
+
+    let input: Box<dyn BufRead + Send> = match (&opt.input, bundled_input) {
+        (Some(path), _) => Box::new(BufReader::new(File::open(path)?)),
+        (None, Some(input)) => Box::new(Cursor::new(input)),
+        (None, None) => Box::new(BufReader::new(io::stdin())),
+    };
+
+    builder = builder.prompt(interactive && !opt.no_prompt);
+
+    builder = match &opt.record_input {
+        Some(path) => builder.input(RecordingReader::new(input, File::create(path)?)),
+        None => builder.input(input),
+    };
+
+    let (mut builder, golden_trace) = configure_outputs(builder, &opt, None)?;
+
+    if opt.debug_output {
+        builder = builder.output_callback(|event| debug_output(&event));
+    }
+
+    if opt.tui {
+        // the terminal UI reads key presses from stdin, so the program can't
+        if reads_stdin {
+            builder = builder.input(io::empty()).prompt(false);
+        }
+
+        return run_tui(builder, opt.seed_stack.clone());
+    }
+
+    let mut interpreter = builder.build();
+
+    #[cfg(feature = "precompile")]
+    if let Some(moves) = interpreter.precompiled_moves() {
+        log::info!("Precompiled {} moves out of blocks", moves);
+    }
+
+    if !opt.seed_stack.is_empty() {
+        interpreter = interpreter.with_initial_stack(opt.seed_stack.clone());
     }
+
+    loop {
+        let reason = if opt.explain {
+            explain(&mut interpreter, opt.invert_lightness)?
+        } else {
+            interpreter.run()?
+        };
+
+        report_halt(&opt, &interpreter, reason, golden_trace.as_ref(), None)?;
+
+        if !opt.run_loop || !prompt_run_again(io::stderr(), io::stdin().lock())? {
+            break;
+        }
+
+        interpreter.reset();
+    }
+
+    Ok(())
+}
+
+/// Ask whether the program should be run again, writing the question to `prompt` and reading
+/// the answer from `answers`.
+///
+/// Anything other than an answer starting with `y`, including the end of the input, is a no.
+fn prompt_run_again(mut prompt: impl Write, mut answers: impl BufRead) -> io::Result<bool> {
+    write!(prompt, "\nrun again? [y/N] ")?;
+    prompt.flush()?;
+
+    let mut line = String::new();
+    answers.read_line(&mut line)?;
+
+    Ok(line.trim_start().starts_with(['y', 'Y']))
 }
@@ -0,0 +1,73 @@
+use super::{Color, Program};
+
+use crate::RietError;
+
+/// The character for every colour, in the same order as their colours.
+///
+/// Normal colours are the uppercase initial of their hue, light colours the lowercase initial,
+/// and dark colours the digits 1 to 6 in hue order. White is a space or a `.`, so it can still
+/// be seen at the end of a line, and black is a `#`.
+#[rustfmt::skip]
+const CHARS: [(char, Color); 20] = {
+    use Color::*;
+
+    [
+        (' ', White), ('#', Black),
+        ('r', LightRed), ('y', LightYellow), ('g', LightGreen),
+        ('c', LightCyan), ('b', LightBlue), ('m', LightMagenta),
+        ('R', Red), ('Y', Yellow), ('G', Green),
+        ('C', Cyan), ('B', Blue), ('M', Magenta),
+        ('1', DarkRed), ('2', DarkYellow), ('3', DarkGreen),
+        ('4', DarkCyan), ('5', DarkBlue), ('6', DarkMagenta),
+    ]
+};
+
+impl Program {
+    /// Construct a new piet program from a grid of characters, one per codel.
+    ///
+    /// Each line is a row of the program, and lines shorter than the longest are padded with
+    /// white. Normal colours are written as the uppercase initial of their hue (`RYGCBM`), light
+    /// colours as the lowercase initial (`rygcbm`) and dark colours as the digits `1` to `6` in
+    /// the same order. White is a space or a `.`, and black is a `#`.
+    pub fn from_ascii(text: &str) -> Result<Self, RietError> {
+        let lines: Vec<&str> = text.lines().collect();
+
+        let rows = lines.len();
+        let cols = lines
+            .iter()
+            .map(|line| line.chars().count())
+            .max()
+            .unwrap_or(0);
+
+        let mut codels = Vec::with_capacity(rows * cols);
+
+        for (row, line) in lines.iter().enumerate() {
+            for (col, c) in line.chars().enumerate() {
+                let color = ascii_color(c).ok_or(RietError::UnknownAsciiColor {
+                    row: row as u32,
+                    col: col as u32,
+                    c,
+                })?;
+
+                codels.push(color);
+            }
+
+            let padding = cols - line.chars().count();
+            codels.extend(std::iter::repeat_n(Color::White, padding));
+        }
+
+        Self::from_grid(rows as u32, cols as u32, codels)
+    }
+}
+
+/// The colour written as `c`
+fn ascii_color(c: char) -> Option<Color> {
+    if c == '.' {
+        return Some(Color::White);
+    }
+
+    CHARS
+        .iter()
+        .find(|&&(ch, _)| ch == c)
+        .map(|&(_, color)| color)
+}
@@ -1,6 +1,6 @@
 use image::Rgb;
 
-use crate::missing_color_white;
+use crate::interpreter::{Operation, OperationTable, Transition, OPERATION_TABLE};
 
 #[allow(dead_code)]
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
@@ -31,6 +31,19 @@ pub enum Color {
 }
 
 impl Color {
+    /// Every colour, light to dark in hue order followed by white and black
+    #[rustfmt::skip]
+    pub const ALL: [Color; 20] = {
+        use Color::*;
+
+        [
+            LightRed, LightYellow, LightGreen, LightCyan, LightBlue, LightMagenta,
+            Red,      Yellow,      Green,      Cyan,      Blue,      Magenta,
+            DarkRed,  DarkYellow,  DarkGreen,  DarkCyan,  DarkBlue,  DarkMagenta,
+            White,    Black,
+        ]
+    };
+
     #[allow(dead_code)]
     pub fn to_rgb8(self) -> Rgb<u8> {
         use Color::*;
@@ -63,10 +76,20 @@ impl Color {
         }
     }
 
-    pub fn from_rgb8(rgb: &Rgb<u8>) -> Self {
+    /// Convert an RGB value to a colour, treating colours outside the piet palette as `missing`
+    pub fn from_rgb8(rgb: &Rgb<u8>, missing: Color) -> Self {
+        Self::from_rgb8_exact(rgb).unwrap_or_else(|| {
+            log::warn!("Encountered an unrecognised colour: {:?}", rgb);
+
+            missing
+        })
+    }
+
+    /// Convert an RGB value to a colour, if it is exactly one of the 20 colours of the palette
+    pub fn from_rgb8_exact(rgb: &Rgb<u8>) -> Option<Self> {
         use Color::*;
 
-        match rgb {
+        let color = match rgb {
             Rgb([0xFF, 0xC0, 0xC0]) => LightRed,
             Rgb([0xFF, 0xFF, 0xC0]) => LightYellow,
             Rgb([0xC0, 0xFF, 0xC0]) => LightGreen,
@@ -91,19 +114,27 @@ impl Color {
             Rgb([0xFF, 0xFF, 0xFF]) => White,
             Rgb([0x00, 0x00, 0x00]) => Black,
 
-            // If the colour is not matched we can interpret it as white
-            Rgb(_) => {
-                log::warn!("Encountered an unrecognised colour: {:?}", rgb);
-                if missing_color_white() {
-                    White
-                } else {
-                    Black
-                }
-            }
-        }
+            Rgb(_) => return None,
+        };
+
+        Some(color)
     }
 
-    fn hue_number(&self) -> Option<i32> {
+    /// Whether the colour is one of the 18 colours with a hue, rather than white or black
+    pub fn is_colored(&self) -> bool {
+        !self.is_white() && !self.is_black()
+    }
+
+    pub fn is_white(&self) -> bool {
+        matches!(self, Color::White)
+    }
+
+    pub fn is_black(&self) -> bool {
+        matches!(self, Color::Black)
+    }
+
+    /// The position of the colour's hue in the hue cycle, from red (0) to magenta (5)
+    pub fn hue_number(&self) -> Option<u32> {
         use Color::*;
 
         #[rustfmt::skip]
@@ -120,13 +151,14 @@ impl Color {
     }
 
     pub fn hue_change(&self, other: &Self) -> Option<u32> {
-        let n1 = self.hue_number()?;
-        let n2 = other.hue_number()?;
+        let n1 = self.hue_number()? as i32;
+        let n2 = other.hue_number()? as i32;
 
         Some((n2 - n1).rem_euclid(6) as u32)
     }
 
-    fn lightness_number(&self) -> Option<i32> {
+    /// The lightness of the colour, from light (0) to dark (2)
+    pub fn lightness_number(&self) -> Option<u32> {
         use Color::*;
 
         #[rustfmt::skip]
@@ -140,9 +172,186 @@ impl Color {
     }
 
     pub fn lightness_change(&self, other: &Self) -> Option<u32> {
-        let n1 = self.lightness_number()?;
-        let n2 = other.lightness_number()?;
+        let n1 = self.lightness_number()? as i32;
+        let n2 = other.lightness_number()? as i32;
 
         Some((n2 - n1).rem_euclid(3) as u32)
     }
-}
\ No newline at end of file
+
+    /// The colour's name in snake case, e.g. `light_red`, which [`Color::from_name`] reads back
+    pub fn name(self) -> &'static str {
+        use Color::*;
+
+        #[rustfmt::skip]
+        match self {
+            LightRed     => "light_red",
+            LightYellow  => "light_yellow",
+            LightGreen   => "light_green",
+            LightCyan    => "light_cyan",
+            LightBlue    => "light_blue",
+            LightMagenta => "light_magenta",
+
+            Red          => "red",
+            Yellow       => "yellow",
+            Green        => "green",
+            Cyan         => "cyan",
+            Blue         => "blue",
+            Magenta      => "magenta",
+
+            DarkRed      => "dark_red",
+            DarkYellow   => "dark_yellow",
+            DarkGreen    => "dark_green",
+            DarkCyan     => "dark_cyan",
+            DarkBlue     => "dark_blue",
+            DarkMagenta  => "dark_magenta",
+
+            White        => "white",
+            Black        => "black",
+        }
+    }
+
+    /// The colour with the given name, as returned by [`Color::name`]
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|color| color.name() == name)
+    }
+
+    /// What happens when the pointer moves from this colour into `other`, using the standard
+    /// operation table
+    pub fn transition(&self, other: &Self) -> Transition {
+        self.transition_with(other, &OPERATION_TABLE)
+    }
+
+    /// What happens when the pointer moves from this colour into `other`, using `table`.
+    ///
+    /// Moving into the same colour is a hue and lightness change of zero, which is always
+    /// [`Transition::NoOp`] as the entry for it in `table` is ignored.
+    pub fn transition_with(&self, other: &Self, table: &OperationTable) -> Transition {
+        if self == other {
+            return Transition::NoOp;
+        }
+
+        let op = self
+            .hue_change(other)
+            .zip(self.lightness_change(other))
+            .and_then(|(hc, lc)| Operation::from_table(table, hc, lc));
+
+        match op {
+            Some(Operation::Noop) | None => Transition::NoOp,
+            Some(op) => Transition::Op(op),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The 18 colours which aren't white or black, with their hue and lightness worked out
+    /// from their position in [`Color::ALL`] rather than with the methods under test
+    fn colored() -> impl Iterator<Item = (Color, usize, usize)> {
+        Color::ALL[..18]
+            .iter()
+            .enumerate()
+            .map(|(i, &color)| (color, i % 6, i / 6))
+    }
+
+    /// Check every transition between two colours against `table`
+    fn check_transitions(table: &OperationTable, transition: impl Fn(Color, Color) -> Transition) {
+        for (from, from_hue, from_lightness) in colored() {
+            for (to, to_hue, to_lightness) in colored() {
+                let hue_change = (to_hue + 6 - from_hue) % 6;
+                let lightness_change = (to_lightness + 3 - from_lightness) % 3;
+
+                let expected = match table[hue_change][lightness_change] {
+                    _ if from == to => Transition::NoOp,
+                    Operation::Noop => Transition::NoOp,
+                    op => Transition::Op(op),
+                };
+
+                assert_eq!(transition(from, to), expected, "{:?} -> {:?}", from, to);
+            }
+        }
+    }
+
+    #[test]
+    fn hue_and_lightness_numbers() {
+        assert_eq!(Color::LightRed.hue_number(), Some(0));
+        assert_eq!(Color::LightRed.lightness_number(), Some(0));
+        assert_eq!(Color::Yellow.hue_number(), Some(1));
+        assert_eq!(Color::Yellow.lightness_number(), Some(1));
+        assert_eq!(Color::DarkCyan.hue_number(), Some(3));
+        assert_eq!(Color::DarkCyan.lightness_number(), Some(2));
+        assert_eq!(Color::Magenta.hue_number(), Some(5));
+        assert_eq!(Color::Magenta.lightness_number(), Some(1));
+
+        for color in [Color::White, Color::Black] {
+            assert_eq!(color.hue_number(), None);
+            assert_eq!(color.lightness_number(), None);
+        }
+
+        for (color, hue, lightness) in colored() {
+            assert_eq!(color.hue_number(), Some(hue as u32), "{:?}", color);
+            assert_eq!(
+                color.lightness_number(),
+                Some(lightness as u32),
+                "{:?}",
+                color
+            );
+        }
+    }
+
+    #[test]
+    fn transitions_between_every_pair_of_colours() {
+        check_transitions(&OPERATION_TABLE, |from, to| from.transition(&to));
+    }
+
+    #[test]
+    fn transitions_between_every_pair_of_colours_with_a_custom_table() {
+        // every entry is different, and the entry for no change isn't a noop
+        let mut table = OPERATION_TABLE;
+        for (i, op) in table.iter_mut().flatten().enumerate() {
+            *op = Operation::ALL[(i + 5) % Operation::ALL.len()];
+        }
+        assert_ne!(table[0][0], Operation::Noop);
+
+        check_transitions(&table, |from, to| from.transition_with(&to, &table));
+    }
+
+    #[test]
+    fn transitions_to_or_from_white_and_black_are_noops() {
+        for color in Color::ALL {
+            for other in [Color::White, Color::Black] {
+                assert_eq!(color.transition(&other), Transition::NoOp);
+                assert_eq!(other.transition(&color), Transition::NoOp);
+            }
+        }
+    }
+
+    #[test]
+    fn names_round_trip() {
+        for color in Color::ALL {
+            assert_eq!(Color::from_name(color.name()), Some(color));
+        }
+
+        let names: std::collections::HashSet<_> = Color::ALL.iter().map(|c| c.name()).collect();
+        assert_eq!(names.len(), Color::ALL.len());
+
+        assert_eq!(Color::from_name("Red"), None);
+        assert_eq!(Color::from_name("light red"), None);
+        assert_eq!(Color::from_name(""), None);
+    }
+
+    #[test]
+    fn same_colour_transitions_are_noops() {
+        // the entry for no hue or lightness change is ignored, even if it isn't a noop
+        let mut table = OPERATION_TABLE;
+        table[0][0] = Operation::Push;
+
+        for color in Color::ALL {
+            assert_eq!(color.transition(&color), Transition::NoOp);
+            assert_eq!(color.transition_with(&color, &table), Transition::NoOp);
+        }
+
+        assert_eq!(Operation::from_changes(0, 0), Some(Operation::Noop));
+    }
+}
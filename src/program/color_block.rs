@@ -1,11 +1,12 @@
 use super::{CodelChooser as CC, Color, DirectionPointer as DP, Point};
 use std::cmp::Ordering::{Equal, Greater, Less};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap};
 
 #[derive(Debug, Clone)]
 pub struct ColorBlock {
     color: Color,
-    area: HashSet<Point>,
+    /// The codels in the block, kept in reading order so iterating them is deterministic
+    area: BTreeSet<Point>,
     edges: HashMap<(DP, CC), Point>,
 }
 
@@ -92,11 +93,11 @@ impl ColorBlock {
         self.color
     }
 
-    pub(super) fn area(&self) -> &HashSet<Point> {
+    pub(super) fn area(&self) -> &BTreeSet<Point> {
         &self.area
     }
 
-    pub(super) fn area_mut(&mut self) -> &mut HashSet<Point> {
+    pub(super) fn area_mut(&mut self) -> &mut BTreeSet<Point> {
         &mut self.area
     }
 
@@ -112,7 +113,23 @@ impl ColorBlock {
         self.area.len()
     }
 
+    /// The codels in the block, in reading order
+    pub fn codels(&self) -> impl Iterator<Item = Point> + '_ {
+        self.area.iter().copied()
+    }
+
     pub fn edge(&self, dp: DP, cc: CC) -> Point {
         self.edges[&(dp, cc)]
     }
+
+    /// The top left and bottom right codels of the smallest rectangle containing the block
+    pub fn bounding_box(&self) -> (Point, Point) {
+        // the edges hold the extreme rows and columns of the block
+        let top = *self.edge(DP::Up, CC::Left).row();
+        let bottom = *self.edge(DP::Down, CC::Left).row();
+        let left = *self.edge(DP::Left, CC::Left).col();
+        let right = *self.edge(DP::Right, CC::Left).col();
+
+        (Point(top, left), Point(bottom, right))
+    }
 }
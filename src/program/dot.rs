@@ -0,0 +1,107 @@
+use super::{CodelChooser, DirectionPointer, Point, Program};
+use crate::interpreter::Operation;
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+
+impl Program {
+    /// Render the graph of colour blocks in Graphviz's DOT language.
+    ///
+    /// Each non-black block is a node filled with its colour and sized by its number of codels.
+    /// Each coloured block has an edge to every block the pointer can move into when leaving
+    /// it, labelled with the operation executed and the DP|CC pairs which take that edge.
+    /// Slides through white regions aren't followed, so those edges end at the white block.
+    pub fn to_dot(&self) -> String {
+        // blocks are named after their first codel in reading order, so the output is stable
+        let mut names = HashMap::new();
+        let mut nodes = vec![];
+
+        for row in 0..self.rows {
+            for col in 0..self.cols {
+                let id = self.block_ids[&Point(row, col)];
+                let block = &self.blocks[id];
+
+                if block.color().is_black() || names.contains_key(&id) {
+                    continue;
+                }
+
+                let name = format!("b{}_{}", row, col);
+                names.insert(id, name.clone());
+                nodes.push((name, block));
+            }
+        }
+
+        let mut dot = String::from("digraph program {\n    node [style=filled];\n");
+
+        for (name, block) in &nodes {
+            let image::Rgb([r, g, b]) = block.color().to_rgb8();
+            let size = 0.5 + (block.num_codels() as f64).sqrt() / 4.0;
+
+            writeln!(
+                dot,
+                "    {} [label=\"{:?}\\n{}\", fillcolor=\"#{:02x}{:02x}{:02x}\", width={:.2}, height={:.2}];",
+                name,
+                block.color(),
+                block.num_codels(),
+                r,
+                g,
+                b,
+                size,
+                size
+            )
+            .unwrap();
+        }
+
+        for (name, block) in &nodes {
+            if !block.color().is_colored() {
+                continue;
+            }
+
+            // the DP|CC pairs leading to each neighbouring block, in a stable order
+            let mut edges: BTreeMap<&str, (Option<Operation>, Vec<String>)> = BTreeMap::new();
+
+            for dp in [
+                DirectionPointer::Right,
+                DirectionPointer::Down,
+                DirectionPointer::Left,
+                DirectionPointer::Up,
+            ] {
+                for cc in [CodelChooser::Left, CodelChooser::Right] {
+                    let next = match block.edge(dp, cc).next_in_direction(dp, self) {
+                        Some(next) => next,
+                        None => continue,
+                    };
+
+                    let next_color = self.codels[self.index_of(&next)];
+
+                    if next_color.is_black() {
+                        continue;
+                    }
+
+                    let op = block.color().transition(&next_color).operation();
+
+                    let target = &names[&self.block_ids[&next]];
+
+                    edges
+                        .entry(target)
+                        .or_insert_with(|| (op, vec![]))
+                        .1
+                        .push(format!("{:?}|{:?}", dp, cc));
+                }
+            }
+
+            for (target, (op, pointers)) in edges {
+                let label = match op {
+                    Some(op) => format!("{}\\n{}", op, pointers.join(", ")),
+                    None => pointers.join(", "),
+                };
+
+                writeln!(dot, "    {} -> {} [label=\"{}\"];", name, target, label).unwrap();
+            }
+        }
+
+        dot.push_str("}\n");
+
+        dot
+    }
+}
@@ -0,0 +1,142 @@
+use std::convert::TryInto;
+
+use crate::RietError;
+
+/// The `tEXt` keyword which piet tools use to store the codel size of a program in a PNG
+pub const CODEL_SIZE_KEY: &str = "piet:codel_size";
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+/// Read the codel size stored in the `tEXt` chunks of a PNG file, if there is one.
+///
+/// Returns `None` when `png` is not a PNG or has no codel size, and an error if the PNG is
+/// truncated or the stored codel size is not a positive number.
+///
+/// Only `tEXt` chunks are read. The physical pixel size in `pHYs` says nothing about how many
+/// pixels make up a codel, so it is ignored.
+pub fn png_codel_size(png: &[u8]) -> Result<Option<u32>, RietError> {
+    if !png.starts_with(&PNG_SIGNATURE) {
+        return Ok(None);
+    }
+
+    let mut rest = &png[PNG_SIGNATURE.len()..];
+
+    // each chunk is a big-endian length, a 4 byte type, the data and a 4 byte CRC
+    while rest.len() >= 8 {
+        let len = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+        let chunk_type = &rest[4..8];
+
+        let data = rest.get(8..8 + len).ok_or_else(|| {
+            RietError::InvalidMetadata("the PNG image ends part way through a chunk".to_string())
+        })?;
+
+        match chunk_type {
+            b"tEXt" => {
+                if let Some(value) = text_value(data, CODEL_SIZE_KEY) {
+                    return match value.trim().parse() {
+                        Ok(codel_size) if codel_size > 0 => Ok(Some(codel_size)),
+                        _ => Err(RietError::InvalidMetadata(format!(
+                            "invalid codel size {:?}",
+                            value
+                        ))),
+                    };
+                }
+            }
+            b"IEND" => break,
+            _ => {}
+        }
+
+        rest = rest.get(8 + len + 4..).unwrap_or_default();
+    }
+
+    Ok(None)
+}
+
+/// The text of a `tEXt` chunk if its keyword is `key`, text chunks are `keyword\0text`
+fn text_value<'a>(data: &'a [u8], key: &str) -> Option<&'a str> {
+    let sep = data.iter().position(|&b| b == 0)?;
+    let (keyword, text) = (&data[..sep], &data[sep + 1..]);
+
+    if keyword == key.as_bytes() {
+        std::str::from_utf8(text).ok()
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A PNG made of `chunks` of a type and data, the CRCs are left as zero as they aren't read
+    fn png(chunks: &[(&[u8; 4], &[u8])]) -> Vec<u8> {
+        let mut png = PNG_SIGNATURE.to_vec();
+
+        for (chunk_type, data) in chunks {
+            png.extend((data.len() as u32).to_be_bytes());
+            png.extend(*chunk_type);
+            png.extend(*data);
+            png.extend([0; 4]);
+        }
+
+        png
+    }
+
+    fn png_with_text(text: &[u8]) -> Vec<u8> {
+        png(&[(b"IHDR", &[0; 13]), (b"tEXt", text), (b"IEND", &[])])
+    }
+
+    fn is_invalid_metadata(result: Result<Option<u32>, RietError>) -> bool {
+        matches!(result, Err(RietError::InvalidMetadata(_)))
+    }
+
+    #[test]
+    fn codel_size_is_read_from_text_chunks() {
+        let png = png(&[
+            (b"IHDR", &[0; 13]),
+            (b"tEXt", b"Comment\0made by hand"),
+            (b"tEXt", b"piet:codel_size\0 4 "),
+            (b"IEND", &[]),
+        ]);
+
+        assert_eq!(png_codel_size(&png).unwrap(), Some(4));
+    }
+
+    #[test]
+    fn pngs_without_a_codel_size_have_none() {
+        assert_eq!(
+            png_codel_size(&png_with_text(b"Comment\x004")).unwrap(),
+            None
+        );
+        assert_eq!(png_codel_size(&png(&[(b"IEND", &[])])).unwrap(), None);
+
+        // chunks after the end of the image aren't read
+        let png = png(&[(b"IEND", &[]), (b"tEXt", b"piet:codel_size\x004")]);
+        assert_eq!(png_codel_size(&png).unwrap(), None);
+    }
+
+    #[test]
+    fn invalid_codel_sizes_are_errors() {
+        for value in [&b"0"[..], b"-2", b"four", b"", b"99999999999"] {
+            let text = [&b"piet:codel_size\0"[..], value].concat();
+
+            assert!(is_invalid_metadata(png_codel_size(&png_with_text(&text))));
+        }
+    }
+
+    #[test]
+    fn truncated_chunks_are_errors() {
+        let png = png_with_text(b"piet:codel_size\x004");
+
+        // cut off part way through the data of the text chunk
+        let truncated = &png[..PNG_SIGNATURE.len() + 25 + 12];
+        assert!(is_invalid_metadata(png_codel_size(truncated)));
+    }
+
+    #[test]
+    fn other_formats_have_no_codel_size() {
+        assert_eq!(png_codel_size(b"").unwrap(), None);
+        assert_eq!(png_codel_size(b"GIF89a").unwrap(), None);
+        assert_eq!(png_codel_size(&PNG_SIGNATURE[..4]).unwrap(), None);
+    }
+}
@@ -4,16 +4,38 @@ pub use point::Point;
 mod color;
 pub use color::Color;
 
+mod ascii;
+
 mod color_block;
 pub use color_block::ColorBlock;
 
-use std::cell::{Ref, RefCell, RefMut};
-use std::collections::HashMap;
-use std::rc::Rc;
+mod dot;
+
+mod metadata;
+pub use metadata::{png_codel_size, CODEL_SIZE_KEY};
+
+mod palette;
+pub use palette::{unknown_colors, UnknownColor};
+
+mod sampler;
+pub use sampler::Sampler;
+
+mod serialize;
+
+mod svg;
 
+use std::collections::{HashMap, HashSet};
+use std::io::Cursor;
+use std::ops::Range;
+use std::path::Path;
+
+use image::io::Reader as ImageReader;
 use image::RgbImage;
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
 
-use crate::interpreter::Interpreter;
+use crate::interpreter::{Interpreter, Operation, OperationTable, Transition, OPERATION_TABLE};
+use crate::RietError;
 
 #[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum DirectionPointer {
@@ -57,24 +79,22 @@ impl CodelChooser {
     }
 }
 
+/// A piet program as a grid of codels, along with the colour blocks they make up.
+///
+/// The derived `PartialEq` also compares the colour blocks, which are numbered in the order
+/// they were built, so the same program loaded in two ways may not compare equal. Use
+/// [`Program::structurally_eq`] to compare the codels alone.
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct Program {
     codels: Vec<Color>,
-    blocks: HashMap<Point, Rc<RefCell<ColorBlock>>>,
+    blocks: Vec<ColorBlock>,
+    /// The index into `blocks` of the block containing each codel
+    block_ids: HashMap<Point, usize>,
     rows: u32,
     cols: u32,
 }
 
 impl Program {
-    fn new(rows: u32, cols: u32) -> Self {
-        Self {
-            codels: vec![Color::White; (rows * cols) as usize],
-            blocks: Default::default(),
-            rows,
-            cols,
-        }
-    }
-
     pub fn rows(&self) -> &u32 {
         &self.rows
     }
@@ -83,197 +103,480 @@ impl Program {
         &self.cols
     }
 
+    /// The number of codels in a program with the given dimensions, or `None` if there are more
+    /// than can be indexed on this platform.
+    ///
+    /// This is computed in `usize` as the product of two `u32`s can easily overflow a `u32`.
+    fn num_codels(rows: u32, cols: u32) -> Option<usize> {
+        (rows as usize).checked_mul(cols as usize)
+    }
+
+    /// The index into the codels, in reading order, of the codel at `p`.
+    ///
+    /// This is the index into the grid given to [`Program::from_grid`].
+    pub fn index_of(&self, p: &Point) -> usize {
+        let Point(row, col) = *p;
+
+        debug_assert!(
+            (row as usize).checked_mul(self.cols as usize).is_some(),
+            "codel index overflowed"
+        );
+
+        row as usize * self.cols as usize + col as usize
+    }
+
+    /// The position of the codel at `index` in the codels, in reading order
+    pub fn point_of(&self, index: usize) -> Point {
+        Self::point_in_grid(index, self.cols)
+    }
+
+    /// The position of the codel at `index` in a grid `cols` codels wide, in reading order
+    fn point_in_grid(index: usize, cols: u32) -> Point {
+        let row = index / cols as usize;
+        let col = index % cols as usize;
+
+        Point(row as u32, col as u32)
+    }
+
     /// Get a reference to a codel in a program
     pub fn get_codel(&self, row: u32, col: u32) -> Option<&Color> {
         if row < self.rows && col < self.cols {
-            self.codels.get((row * self.cols + col) as usize)
+            self.codels.get(self.index_of(&Point(row, col)))
         } else {
             None
         }
     }
 
-    /// get a mutable reference to a codel in a program
-    fn get_codel_mut(&mut self, row: u32, col: u32) -> Option<&mut Color> {
+    /// Whether the two programs have the same dimensions and codels, ignoring how their colour
+    /// blocks were built as these are derived from the codels
+    pub fn structurally_eq(&self, other: &Self) -> bool {
+        self.rows == other.rows && self.cols == other.cols && self.codels == other.codels
+    }
+
+    /// A fingerprint of the program's dimensions and codels, for caching and deduplication.
+    ///
+    /// This is a 64-bit FNV-1a hash so it is the same across runs, platforms and versions of
+    /// rust, and doesn't depend on the colour blocks as these are derived from the codels.
+    pub fn fingerprint(&self) -> u64 {
+        const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+        const FNV_PRIME: u64 = 0x100000001b3;
+
+        let dimensions = self.rows.to_le_bytes().into_iter().chain(self.cols.to_le_bytes());
+        let codels = self.codels.iter().flat_map(|codel| codel.to_rgb8().0);
+
+        dimensions
+            .chain(codels)
+            .fold(FNV_OFFSET_BASIS, |hash, byte| {
+                (hash ^ byte as u64).wrapping_mul(FNV_PRIME)
+            })
+    }
+
+    /// The number of colour blocks in the program
+    pub fn num_blocks(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// The fraction of coloured blocks which cover less than a quarter of their bounding box.
+    ///
+    /// Reading an image with the wrong codel size gives many thin or jagged blocks, so a high
+    /// fraction suggests the codel size is wrong.
+    pub fn jagged_block_fraction(&self) -> f64 {
+        let colored: Vec<_> = self
+            .blocks
+            .iter()
+            .filter(|block| block.color().is_colored())
+            .collect();
+
+        if colored.is_empty() {
+            return 0.0;
+        }
+
+        let jagged = colored
+            .iter()
+            .filter(|block| {
+                let (Point(top, left), Point(bottom, right)) = block.bounding_box();
+                let box_area = (bottom - top + 1) as usize * (right - left + 1) as usize;
+
+                box_area > 4 * block.num_codels()
+            })
+            .count();
+
+        jagged as f64 / colored.len() as f64
+    }
+
+    /// Whether any codel in the program has a hue, without one no operation can ever execute
+    pub fn has_colored_codels(&self) -> bool {
+        self.codels.iter().any(Color::is_colored)
+    }
+
+    /// Whether the pointer can leave the coloured block containing `point` with any DP and CC.
+    ///
+    /// A block which can't be left traps the pointer as soon as it enters it. White and black
+    /// blocks are never left this way, so are always reported as escapable.
+    pub fn block_can_be_left(&self, point: &Point) -> bool {
+        if !matches!(self.get_color_block(point), Some(block) if block.color().is_colored()) {
+            return true;
+        }
+
+        let dps = [
+            DirectionPointer::Right,
+            DirectionPointer::Down,
+            DirectionPointer::Left,
+            DirectionPointer::Up,
+        ];
+
+        dps.into_iter().any(|dp| {
+            [CodelChooser::Left, CodelChooser::Right]
+                .into_iter()
+                .any(|cc| self.next_move(point, dp, cc).is_some())
+        })
+    }
+
+    /// Where the pointer moves when it leaves the block containing `from` with the given DP
+    /// and CC, without toggling the CC or rotating the DP if it is restricted.
+    ///
+    /// Returns the codel on the edge of the block the pointer leaves from, followed by the codel
+    /// it moves into and its colour. `None` is returned if there is no block containing `from`,
+    /// or the pointer would move off the edge of the program or into a black codel.
+    pub fn next_move(
+        &self,
+        from: &Point,
+        dp: DirectionPointer,
+        cc: CodelChooser,
+    ) -> Option<(Point, Point, Color)> {
+        let edge = self.get_color_block(from)?.edge(dp, cc);
+        let next = edge.next_in_direction(dp, self)?;
+        let next_color = self.codels[self.index_of(&next)];
+
+        (!next_color.is_black()).then_some((edge, next, next_color))
+    }
+
+    /// Every operation executed by moving directly between two coloured blocks of the program.
+    ///
+    /// Operations missing from this can never be executed by the standard table, as sliding
+    /// across white never executes an operation.
+    pub fn reachable_operations(&self) -> HashSet<Operation> {
+        self.reachable_operations_with(&OPERATION_TABLE)
+    }
+
+    /// Every operation executed by moving directly between two coloured blocks of the program,
+    /// looking up the operation for each colour change in `table`
+    pub fn reachable_operations_with(&self, table: &OperationTable) -> HashSet<Operation> {
+        let dps = [
+            DirectionPointer::Right,
+            DirectionPointer::Down,
+            DirectionPointer::Left,
+            DirectionPointer::Up,
+        ];
+
+        let mut ops = HashSet::new();
+
+        for block in &self.blocks {
+            let color = block.color();
+
+            if !color.is_colored() {
+                continue;
+            }
+
+            for dp in dps {
+                for cc in [CodelChooser::Left, CodelChooser::Right] {
+                    let next_color = match self.next_move(&block.edge(dp, cc), dp, cc) {
+                        Some((_, _, next_color)) => next_color,
+                        None => continue,
+                    };
+
+                    if let Transition::Op(op) = color.transition_with(&next_color, table) {
+                        ops.insert(op);
+                    }
+                }
+            }
+        }
+
+        ops
+    }
+
+    /// The index of the colour block containing `point`, which is stable for the life of the
+    /// program
+    #[cfg(feature = "precompile")]
+    pub(crate) fn block_id(&self, point: &Point) -> Option<usize> {
+        self.block_ids.get(point).copied()
+    }
+
+    /// Get a reference to a color block in a program
+    pub fn get_color_block(&self, point: &Point) -> Option<&ColorBlock> {
+        self.block_ids.get(point).map(|&id| &self.blocks[id])
+    }
+
+    /// Get the color block covering the codel at the given row and column
+    pub fn block_at(&self, row: u32, col: u32) -> Option<&ColorBlock> {
         if row < self.rows && col < self.cols {
-            self.codels.get_mut((row * self.cols + col) as usize)
+            self.get_color_block(&Point(row, col))
         } else {
             None
         }
     }
 
-    /// Get a reference to a color block in a program
-    pub fn get_color_block(&self, point: &Point) -> Option<Ref<ColorBlock>> {
-        self.blocks.get(point).map(|b| (**b).borrow())
+    /// Get a mutable reference to a color block in a program
+    pub fn get_color_block_mut(&mut self, point: &Point) -> Option<&mut ColorBlock> {
+        self.block_ids.get(point).map(|&id| &mut self.blocks[id])
     }
 
-    /// Get a reference to a color block in a program
-    pub fn get_color_block_mut(&self, point: &Point) -> Option<RefMut<ColorBlock>> {
-        self.blocks.get(point).map(|b| (**b).borrow_mut())
-    }
+    /// Construct a new piet program from an imagebuffer containing a piet image, reading the
+    /// colour of each codel with `sampler`. Pixels outside the piet palette are read as `missing`.
+    pub fn new_from_imagebuffer(
+        img: &RgbImage,
+        codel_size: u32,
+        sampler: Sampler,
+        missing: Color,
+    ) -> Result<Self, RietError> {
+        if codel_size == 0 {
+            return Err(RietError::InvalidCodelSize);
+        }
 
-    /// Construct a new piet program from an imagebuffer containing a piet image.
-    pub fn new_from_imagebuffer(img: &RgbImage, codel_size: u32) -> Self {
-        let mut program = if codel_size == 1 {
+        if codel_size == 1 {
             // special case a codel size of 1 for efficiency
-            Self {
-                codels: img.pixels().map(Color::from_rgb8).collect(),
-                blocks: Default::default(),
-                rows: img.height(),
-                cols: img.width(),
-            }
+            let codels = img
+                .pixels()
+                .map(|rgb| Color::from_rgb8(rgb, missing))
+                .collect();
+
+            Self::from_grid(img.height(), img.width(), codels)
         } else {
             let cols = img.width() / codel_size;
             let rows = img.height() / codel_size;
 
-            let mut program = Self::new(rows, cols);
+            let span = |i: u32| i * codel_size..(i + 1) * codel_size;
+
+            Self::sample_codels(img, rows, cols, sampler, missing, span)
+        }
+    }
+
+    /// Construct a new piet program from an image which was scaled by a non-integer factor,
+    /// e.g. a screenshot taken at 3.5x zoom, so each codel covers `codel_size` pixels.
+    ///
+    /// The pixels of the `i`th codel along each axis are `round(i * codel_size)` up to
+    /// `round((i + 1) * codel_size)`, so codels are one pixel wider or narrower than each other
+    /// to keep their boundaries in line with the scaled image. A codel at the edge of the image
+    /// is kept if at least half of it is in the image. Pixels outside the piet palette are read
+    /// as `missing`.
+    pub fn new_from_imagebuffer_scaled(
+        img: &RgbImage,
+        codel_size: f64,
+        sampler: Sampler,
+        missing: Color,
+    ) -> Result<Self, RietError> {
+        // this also rejects NaN
+        if !(codel_size >= 1.0 && codel_size.is_finite()) {
+            return Err(RietError::InvalidCodelSize);
+        }
 
-            for row in 0..rows {
-                for col in 0..cols {
-                    // vote for the color of the codel
-                    let mut votes: HashMap<Color, u32> = HashMap::new();
+        let cols = (img.width() as f64 / codel_size).round() as u32;
+        let rows = (img.height() as f64 / codel_size).round() as u32;
 
-                    let tl_x = col * codel_size;
-                    let tl_y = row * codel_size;
+        let boundary = |i: u32| (i as f64 * codel_size).round() as u32;
 
-                    for x in tl_x..tl_x + codel_size {
-                        for y in tl_y..tl_y + codel_size {
-                            let color = Color::from_rgb8(img.get_pixel(x, y));
-                            *votes.entry(color).or_insert(0) += 1;
-                        }
-                    }
+        let span = |i: u32| boundary(i)..boundary(i + 1);
+
+        Self::sample_codels(img, rows, cols, sampler, missing, span)
+    }
+
+    /// Read the colour of each codel in a `rows` by `cols` grid from `img` with `sampler`, where
+    /// `span` gives the pixels covered by the codel at an index along either axis
+    fn sample_codels(
+        img: &RgbImage,
+        rows: u32,
+        cols: u32,
+        sampler: Sampler,
+        missing: Color,
+        span: impl Fn(u32) -> Range<u32> + Sync,
+    ) -> Result<Self, RietError> {
+        // nothing has been sampled when the image is too large to hold its codels
+        let num_codels = Self::num_codels(rows, cols).ok_or(RietError::GridSize {
+            rows,
+            cols,
+            found: 0,
+        })?;
+
+        let mut codels = vec![Color::White; num_codels];
 
-                    // the colour of the codel is the one with the most votes
-                    if let Some((codel_color, _)) =
-                        votes.into_iter().max_by_key(|&(_, votes)| votes)
-                    {
-                        let codel = program.get_codel_mut(row, col).unwrap();
+        // each codel is sampled independently of the others
+        let sample = |(i, codel): (usize, &mut Color)| {
+            let Point(row, col) = Self::point_in_grid(i, cols);
+
+            // a codel at the edge of a scaled image may only partly cover it
+            let xs = span(col).start..span(col).end.min(img.width());
+            let ys = span(row).start..span(row).end.min(img.height());
+
+            match sampler {
+                Sampler::Majority => {
+                    if let Some(codel_color) = Self::vote_codel_color(img, xs, ys, missing) {
                         *codel = codel_color;
                     }
                 }
+                Sampler::Center => {
+                    *codel = Self::center_codel_color(img, xs, ys, missing);
+                }
             }
+        };
+
+        #[cfg(feature = "rayon")]
+        codels.par_iter_mut().enumerate().for_each(sample);
+
+        #[cfg(not(feature = "rayon"))]
+        codels.iter_mut().enumerate().for_each(sample);
+
+        Self::from_grid(rows, cols, codels)
+    }
+
+    /// Construct a new piet program from an encoded image held in memory, in any format the
+    /// `image` crate can detect. The colour of each codel is decided by majority vote, and pixels
+    /// outside the piet palette are read as white.
+    pub fn from_bytes(bytes: &[u8], codel_size: u32) -> Result<Self, RietError> {
+        let img = ImageReader::new(Cursor::new(bytes))
+            .with_guessed_format()?
+            .decode()?;
+
+        Self::new_from_imagebuffer(&img.to_rgb8(), codel_size, Sampler::Majority, Color::White)
+    }
 
-            program
+    /// Construct a new piet program from a grid of codels, stored row by row.
+    ///
+    /// This is for tools which already have the codels, e.g. because they generate programs,
+    /// and so don't need to go through an image first.
+    pub fn from_grid(rows: u32, cols: u32, grid: Vec<Color>) -> Result<Self, RietError> {
+        if rows == 0 || cols == 0 {
+            return Err(RietError::EmptyProgram);
+        }
+
+        if Self::num_codels(rows, cols) != Some(grid.len()) {
+            return Err(RietError::GridSize {
+                rows,
+                cols,
+                found: grid.len(),
+            });
+        }
+
+        let mut program = Self {
+            codels: grid,
+            blocks: Default::default(),
+            block_ids: Default::default(),
+            rows,
+            cols,
         };
 
         // we now need to fill in the code blocks
-        for col in 0..program.cols {
-            for row in 0..program.rows {
-                let codel_color = *program.get_codel(row, col).unwrap();
-
-                // represent valid neighbours by a pair of Some values
-                let neighbours = [
-                    (row.checked_sub(1), Some(col)),
-                    (
-                        if row + 1 < program.rows {
-                            Some(row + 1)
-                        } else {
-                            None
-                        },
-                        Some(col),
-                    ),
-                    (Some(row), col.checked_sub(1)),
-                    (
-                        Some(row),
-                        if col + 1 < program.cols {
-                            Some(col + 1)
-                        } else {
-                            None
-                        },
-                    ),
-                ];
-
-                let compatible_neighbours: Option<Vec<Point>> = neighbours.into_iter()
-                    .map(|(r, c)| {
-                        r.and_then(|row| {
-                            c.map( |col| Point(row, col))
-                        })
-                    })
-                    .filter(|p| {
-                        p.as_ref().and_then(|point| {
-                            let b = program.get_color_block(point);
-                            b.map(|block| block.color() == codel_color)
-                        }).unwrap_or(false)
-                    })
-                    .collect();
-
-                // if we have any compatible neighbours merge them
-                if let Some(cn) = compatible_neighbours.as_ref() {
-                    for w in cn.array_windows::<2>() {
-                        let [a, b] = w;
-                        program.merge_color_blocks(a, b);
-                    }
-                }
-                
-                if let Some(neigh) = compatible_neighbours.as_ref().and_then(|neighs| neighs.get(0)) {
-                    program.get_color_block_mut(neigh).unwrap()
-                        .add_codel(row, col);
-                    
-                    program.blocks.insert(Point(row, col), program.blocks.get(neigh).unwrap().clone());
-                } else {
-                    program.blocks.insert(
-                        Point(row, col),
-                        Rc::new(RefCell::new(ColorBlock::new(codel_color, row, col))),
-                    );
-                }
+        program.fill_color_blocks();
+
+        if !program.has_colored_codels() {
+            log::warn!("The program contains no colored codels so it can never execute an operation");
+        }
+
+        Ok(program)
+    }
+
+    /// Determine the colour of a codel by majority vote over the pixels it covers.
+    fn vote_codel_color(
+        img: &RgbImage,
+        xs: Range<u32>,
+        ys: Range<u32>,
+        missing: Color,
+    ) -> Option<Color> {
+        let mut votes: HashMap<Color, u32> = HashMap::new();
+
+        for x in xs {
+            for y in ys.clone() {
+                let color = Color::from_rgb8(img.get_pixel(x, y), missing);
+                *votes.entry(color).or_insert(0) += 1;
             }
         }
 
-        program
+        // the colour of the codel is the one with the most votes
+        votes
+            .into_iter()
+            .max_by_key(|&(_, votes)| votes)
+            .map(|(codel_color, _)| codel_color)
     }
 
-    /// merge two color blocks together
-    fn merge_color_blocks(&mut self, point1: &Point, point2: &Point) {
-        // steps:
-        // -1. check we are not merging a block into itself.
-        // 0. determine which of the points is bigger.
-        // 1. add all the points in the area of the smaller one to the area of the bigger one.
-        // 2. go through the edges of the smaller one and determine which, if any, are more extreme than those of the parent color block.
-        // 3. ensure entries in the smaller block point to the new block.
+    /// Determine the colour of a codel from the pixel at its centre.
+    fn center_codel_color(img: &RgbImage, xs: Range<u32>, ys: Range<u32>, missing: Color) -> Color {
+        let x = xs.start + (xs.end - xs.start) / 2;
+        let y = ys.start + (ys.end - ys.start) / 2;
 
-        // -1. check we are not merging a block into itself.
-        if self.blocks.get(point1).eq(&self.blocks.get(point2)) {
-            return;
-        }
+        Color::from_rgb8(img.get_pixel(x, y), missing)
+    }
+
+    /// Find every colour block in the program.
+    ///
+    /// Each block is flood filled from the first codel found which isn't yet part of a block,
+    /// so every codel is visited once however the blocks are shaped, and a program made of a
+    /// single colour is found to be a single block in one pass.
+    fn fill_color_blocks(&mut self) {
+        for row in 0..self.rows {
+            for col in 0..self.cols {
+                if self.block_ids.contains_key(&Point(row, col)) {
+                    continue;
+                }
 
-        // 0. determine which of the points is bigger.
-        let (bigger_point, smaller_point) = {
-            let block1 = self
-                .blocks
-                .get(point1)
-                .expect("Tried to merge a non-existant block.");
-            let block2 = self
-                .blocks
-                .get(point2)
-                .expect("Tried to merge a non-existant block.");
-
-            if block1 < block2 {
-                (point2, point1)
-            } else {
-                (point1, point2)
+                self.insert_color_block(row, col);
             }
-        };
+        }
+    }
+
+    /// Build the colour block containing the codel at the given row and column, and map each
+    /// of its codels to it
+    fn insert_color_block(&mut self, row: u32, col: u32) {
+        let block = self.flood_fill(row, col);
+        let id = self.blocks.len();
 
-        // declare a new scope for mutating the program
-        {
-            let mut bigger = self.get_color_block_mut(bigger_point).unwrap();
-            let smaller = self.get_color_block(smaller_point).unwrap();
+        for point in block.area() {
+            self.block_ids.insert(*point, id);
+        }
 
-            // 1. extend the bigger area with the points from the smaller one
-            bigger.area_mut().extend(smaller.area().iter());
+        self.blocks.push(block);
+    }
 
-            // 2. go through the edges of the smaller one and determine which, if any, are more extreme than those of the parent color block.
-            for point in smaller.edges().values() {
-                bigger.add_codel(*point.row(), *point.col());
-            }
+    /// Make sure the codel at `point` has a colour block, rebuilding it if it is missing.
+    ///
+    /// Every codel is given a block when the program is built, so this only recovers from bugs
+    /// which leave the blocks incomplete, and logs a warning when it does.
+    pub fn ensure_color_block(&mut self, point: &Point) {
+        let Point(row, col) = *point;
+
+        if self.block_ids.contains_key(point) || self.get_codel(row, col).is_none() {
+            return;
         }
 
-        // 3. ensure entries in the smaller block point to the bigger block.
-        let bigger_block = self.blocks.get(bigger_point).unwrap().clone();
-        let smaller_area = self.get_color_block(smaller_point).unwrap().area().clone();
+        log::warn!("The colour block for {:?} was missing, rebuilding it", point);
 
-        for point in smaller_area {
-            self.blocks.insert(point, bigger_block.clone());
+        self.insert_color_block(row, col);
+    }
+
+    /// Build the colour block containing the codel at the given row and column
+    fn flood_fill(&self, row: u32, col: u32) -> ColorBlock {
+        let color = *self.get_codel(row, col).unwrap();
+
+        let mut block = ColorBlock::new(color, row, col);
+        let mut to_visit = vec![Point(row, col)];
+
+        while let Some(point) = to_visit.pop() {
+            let neighbours = [
+                DirectionPointer::Right,
+                DirectionPointer::Down,
+                DirectionPointer::Left,
+                DirectionPointer::Up,
+            ]
+            .into_iter()
+            .filter_map(|dp| point.next_in_direction(dp, self));
+
+            for neighbour @ Point(row, col) in neighbours {
+                if self.get_codel(row, col) == Some(&color) && !block.area().contains(&neighbour) {
+                    block.add_codel(row, col);
+                    to_visit.push(neighbour);
+                }
+            }
         }
+
+        block
     }
 
     pub fn into_interpreter(self) -> Interpreter {
@@ -281,8 +584,7 @@ impl Program {
     }
 
     /// Save the codels to an image, with each codel represented with one pixel
-    #[allow(dead_code)]
-    pub fn save_codels(&self, path: &str) -> anyhow::Result<()> {
+    pub fn save_codels(&self, path: impl AsRef<Path>) -> Result<(), RietError> {
         let mut colours = vec![];
 
         for codel in self.codels.iter() {
@@ -293,12 +595,144 @@ impl Program {
             colours.push(b);
         }
 
+        let found = self.codels.len();
         let buf: image::RgbImage =
-            image::ImageBuffer::from_vec(*self.cols(), *self.rows(), colours)
-                .ok_or_else(|| anyhow::anyhow!("Failed to encode codels as pixels."))?;
+            image::ImageBuffer::from_vec(*self.cols(), *self.rows(), colours).ok_or(
+                RietError::GridSize {
+                    rows: self.rows,
+                    cols: self.cols,
+                    found,
+                },
+            )?;
 
         buf.save(path)?;
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// `count` programs of up to 8 by 8 codels of random colours, which are the same on every run
+    pub(crate) fn random_programs(count: usize) -> impl Iterator<Item = Program> {
+        // a small linear congruential generator
+        let mut seed: u64 = 0x5eed;
+        let mut next = move || {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (seed >> 33) as usize
+        };
+
+        (0..count).map(move |_| {
+            let rows = 1 + next() % 8;
+            let cols = 1 + next() % 8;
+            let codels = (0..rows * cols)
+                .map(|_| Color::ALL[next() % Color::ALL.len()])
+                .collect();
+
+            Program::from_grid(rows as u32, cols as u32, codels).unwrap()
+        })
+    }
+
+    fn is_empty_program<T: std::fmt::Debug>(result: Result<T, RietError>) -> bool {
+        matches!(result, Err(RietError::EmptyProgram))
+    }
+
+    #[test]
+    fn grids_without_codels_are_empty_programs() {
+        assert!(is_empty_program(Program::from_grid(0, 0, vec![])));
+        assert!(is_empty_program(Program::from_grid(1, 0, vec![])));
+        assert!(is_empty_program(Program::from_grid(0, 1, vec![])));
+        assert!(is_empty_program(Program::from_ascii("")));
+    }
+
+    #[test]
+    fn grids_with_the_wrong_number_of_codels_are_errors() {
+        let is_grid_size = |result| matches!(result, Err(RietError::GridSize { .. }));
+
+        assert!(is_grid_size(Program::from_grid(2, 2, vec![Color::Red; 3])));
+        assert!(is_grid_size(Program::from_grid(2, 2, vec![Color::Red; 5])));
+        assert!(is_grid_size(Program::from_grid(
+            u32::MAX,
+            u32::MAX,
+            vec![Color::Red]
+        )));
+    }
+
+    #[test]
+    fn images_without_codels_are_empty_programs() {
+        for (width, height) in [(0, 0), (1, 0), (0, 1)] {
+            let img = RgbImage::new(width, height);
+
+            for sampler in [Sampler::Majority, Sampler::Center] {
+                for codel_size in [1, 2] {
+                    let program =
+                        Program::new_from_imagebuffer(&img, codel_size, sampler, Color::White);
+                    assert!(is_empty_program(program));
+                }
+            }
+        }
+
+        // an image smaller than a single codel has no whole codels in it
+        let img = RgbImage::new(1, 1);
+        let program = Program::new_from_imagebuffer(&img, 2, Sampler::Majority, Color::White);
+        assert!(is_empty_program(program));
+    }
+
+    #[test]
+    fn colours_outside_the_palette_are_read_as_the_missing_colour() {
+        let img = RgbImage::from_pixel(2, 2, image::Rgb([0x12, 0x34, 0x56]));
+
+        for missing in [Color::White, Color::Black] {
+            for sampler in [Sampler::Majority, Sampler::Center] {
+                for codel_size in [1, 2] {
+                    let program =
+                        Program::new_from_imagebuffer(&img, codel_size, sampler, missing).unwrap();
+                    assert!(program.codels.iter().all(|&codel| codel == missing));
+                }
+
+                let program =
+                    Program::new_from_imagebuffer_scaled(&img, 1.5, sampler, missing).unwrap();
+                assert!(program.codels.iter().all(|&codel| codel == missing));
+            }
+        }
+    }
+
+    #[test]
+    fn reachable_operations_are_looked_up_in_the_given_table() {
+        let program = Program::from_ascii("RY\n").unwrap();
+
+        assert_eq!(
+            program.reachable_operations(),
+            HashSet::from([Operation::Add, Operation::InChar])
+        );
+        assert_eq!(
+            program.reachable_operations_with(&[[Operation::Not; 3]; 6]),
+            HashSet::from([Operation::Not])
+        );
+    }
+
+    #[test]
+    fn neighbouring_codels_of_the_same_colour_are_in_the_same_block() {
+        for program in random_programs(200) {
+            for row in 0..program.rows {
+                for col in 0..program.cols {
+                    let color = program.get_codel(row, col).unwrap();
+                    let block = match program.get_color_block(&Point(row, col)) {
+                        Some(block) if color.is_colored() => block,
+                        _ => continue,
+                    };
+
+                    for (row, col) in [(row + 1, col), (row, col + 1)] {
+                        if program.get_codel(row, col) == Some(color) {
+                            assert!(block.codels().any(|codel| codel == Point(row, col)));
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
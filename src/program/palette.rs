@@ -0,0 +1,45 @@
+use super::Color;
+
+use std::collections::HashMap;
+
+use image::{Rgb, RgbImage};
+
+/// A colour found in an image which isn't part of the piet palette
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct UnknownColor {
+    pub rgb: Rgb<u8>,
+    /// The number of pixels with this colour
+    pub count: usize,
+    /// The position (x, y) of the first pixel found with this colour
+    pub sample: (u32, u32),
+}
+
+/// Find every colour in `img` which isn't part of the piet palette, most frequent first.
+///
+/// This looks at the pixels before they are grouped into codels, so shows exactly which
+/// colours would be treated as white or black.
+pub fn unknown_colors(img: &RgbImage) -> Vec<UnknownColor> {
+    let mut colors: HashMap<Rgb<u8>, UnknownColor> = HashMap::new();
+
+    for (x, y, rgb) in img.enumerate_pixels() {
+        if Color::from_rgb8_exact(rgb).is_some() {
+            continue;
+        }
+
+        colors
+            .entry(*rgb)
+            .or_insert(UnknownColor {
+                rgb: *rgb,
+                count: 0,
+                sample: (x, y),
+            })
+            .count += 1;
+    }
+
+    let mut colors: Vec<_> = colors.into_values().collect();
+
+    // pixels are visited in reading order, so ties are broken by where the colour first appears
+    colors.sort_by_key(|c| (std::cmp::Reverse(c.count), c.sample.1, c.sample.0));
+
+    colors
+}
@@ -1,6 +1,7 @@
 use super::{DirectionPointer as DP, Program};
 
-#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+/// A codel's position as its row and column, ordered in reading order
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Point(pub u32, pub u32);
 
 impl Point {
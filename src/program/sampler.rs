@@ -0,0 +1,39 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::RietError;
+
+/// How the colour of a codel is read from the pixels it covers
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Sampler {
+    /// The colour most of the pixels have, which copes with noise in the image
+    #[default]
+    Majority,
+    /// The colour of the pixel in the centre of the codel, which is faster and isn't skewed
+    /// by antialiasing at the edges of codels in crisply rendered images
+    Center,
+}
+
+impl FromStr for Sampler {
+    type Err = RietError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "majority" => Ok(Sampler::Majority),
+            "center" => Ok(Sampler::Center),
+            _ => Err(RietError::UnknownSampler(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for Sampler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        #[rustfmt::skip]
+        match self {
+            Sampler::Majority => write!(f, "majority")?,
+            Sampler::Center   => write!(f, "center")?,
+        }
+
+        Ok(())
+    }
+}
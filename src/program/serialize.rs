@@ -0,0 +1,189 @@
+use super::{Color, Program};
+
+use std::io::{Read, Write};
+
+use crate::RietError;
+
+/// The bytes every serialized program starts with, followed by the format version
+const MAGIC: &[u8; 4] = b"RIET";
+const VERSION: u8 = 2;
+
+/// Every colour, in the order they are numbered when serialized
+#[rustfmt::skip]
+const COLORS: [Color; 20] = {
+    use Color::*;
+
+    [
+        White, Black,
+        LightRed, LightYellow, LightGreen, LightCyan, LightBlue, LightMagenta,
+        Red, Yellow, Green, Cyan, Blue, Magenta,
+        DarkRed, DarkYellow, DarkGreen, DarkCyan, DarkBlue, DarkMagenta,
+    ]
+};
+
+impl Program {
+    /// Write the program in a compact binary format, which can be read back much faster than
+    /// decoding an image.
+    ///
+    /// `settings` describes how the program was read, e.g. its codel size and sampler, and
+    /// reading the program back fails unless it is given the same settings. This stops a cache
+    /// being used after the settings it was built with have changed.
+    ///
+    /// The format is `RIET`, a version byte, the length of the settings as a little-endian
+    /// `u16` followed by the settings, the rows and columns as little-endian `u32`s, then one
+    /// byte per codel in reading order. The colour blocks aren't written, as they are rebuilt
+    /// from the codels when the program is read.
+    pub fn serialize_to(&self, mut writer: impl Write, settings: &str) -> Result<(), RietError> {
+        let settings_len: u16 = settings.len().try_into().map_err(|_| {
+            RietError::InvalidSerializedProgram("the settings are too long".to_string())
+        })?;
+
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[VERSION])?;
+        writer.write_all(&settings_len.to_le_bytes())?;
+        writer.write_all(settings.as_bytes())?;
+        writer.write_all(&self.rows.to_le_bytes())?;
+        writer.write_all(&self.cols.to_le_bytes())?;
+
+        let codels: Vec<u8> = self
+            .codels
+            .iter()
+            .map(|codel| COLORS.iter().position(|c| c == codel).unwrap() as u8)
+            .collect();
+
+        writer.write_all(&codels)?;
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    /// Read a program written by [`Program::serialize_to`] with the same `settings`.
+    ///
+    /// The codels are read as they arrive rather than allocated up front from the dimensions
+    /// in the header, so a corrupt header can't exhaust the memory.
+    pub fn deserialize_from(mut reader: impl Read, settings: &str) -> Result<Self, RietError> {
+        let mut header = [0; 7];
+        reader.read_exact(&mut header)?;
+
+        if &header[..4] != MAGIC {
+            return Err(RietError::InvalidSerializedProgram(
+                "the file is not a serialized piet program".to_string(),
+            ));
+        }
+
+        if header[4] != VERSION {
+            return Err(RietError::InvalidSerializedProgram(format!(
+                "unsupported version {}",
+                header[4]
+            )));
+        }
+
+        let settings_len = u16::from_le_bytes([header[5], header[6]]);
+        let mut written_settings = vec![0; settings_len as usize];
+        reader.read_exact(&mut written_settings)?;
+
+        if written_settings != settings.as_bytes() {
+            return Err(RietError::InvalidSerializedProgram(format!(
+                "it was written with different settings: {}",
+                String::from_utf8_lossy(&written_settings)
+            )));
+        }
+
+        let mut dimensions = [0; 8];
+        reader.read_exact(&mut dimensions)?;
+
+        let rows = u32::from_le_bytes([dimensions[0], dimensions[1], dimensions[2], dimensions[3]]);
+        let cols = u32::from_le_bytes([dimensions[4], dimensions[5], dimensions[6], dimensions[7]]);
+
+        let num_codels = rows as u64 * cols as u64;
+        let mut codels = vec![];
+        reader.take(num_codels).read_to_end(&mut codels)?;
+
+        if codels.len() as u64 != num_codels {
+            return Err(RietError::InvalidSerializedProgram(format!(
+                "expected {} codels for a {}x{} program, found {}",
+                num_codels,
+                rows,
+                cols,
+                codels.len()
+            )));
+        }
+
+        let grid = codels
+            .into_iter()
+            .map(|i| {
+                COLORS
+                    .get(i as usize)
+                    .copied()
+                    .ok_or_else(|| {
+                        RietError::InvalidSerializedProgram(format!("invalid colour {}", i))
+                    })
+            })
+            .collect::<Result<_, _>>()?;
+
+        Self::from_grid(rows, cols, grid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn program() -> Program {
+        Program::from_ascii("RRy\n#b5\n").unwrap()
+    }
+
+    fn serialized(settings: &str) -> Vec<u8> {
+        let mut bytes = vec![];
+        program().serialize_to(&mut bytes, settings).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn round_trip() {
+        let read = Program::deserialize_from(&serialized("codel size 1")[..], "codel size 1");
+
+        assert!(read.unwrap().structurally_eq(&program()));
+    }
+
+    #[test]
+    fn different_settings_are_rejected() {
+        let read = Program::deserialize_from(&serialized("codel size 1")[..], "codel size 2");
+
+        assert!(matches!(read, Err(RietError::InvalidSerializedProgram(_))));
+    }
+
+    #[test]
+    fn huge_dimensions_without_codels_are_rejected() {
+        let mut bytes = serialized("");
+        // the header is 7 bytes, then the empty settings and the dimensions
+        bytes.truncate(7);
+        bytes.extend(u32::MAX.to_le_bytes());
+        bytes.extend(u32::MAX.to_le_bytes());
+        bytes.extend([0; 16]);
+
+        let read = Program::deserialize_from(&bytes[..], "");
+
+        assert!(matches!(read, Err(RietError::InvalidSerializedProgram(_))));
+    }
+
+    #[test]
+    fn truncated_codels_are_rejected() {
+        let mut bytes = serialized("");
+        bytes.pop();
+
+        let read = Program::deserialize_from(&bytes[..], "");
+
+        assert!(matches!(read, Err(RietError::InvalidSerializedProgram(_))));
+    }
+
+    #[test]
+    fn unknown_colours_are_rejected() {
+        let mut bytes = serialized("");
+        *bytes.last_mut().unwrap() = COLORS.len() as u8;
+
+        let read = Program::deserialize_from(&bytes[..], "");
+
+        assert!(matches!(read, Err(RietError::InvalidSerializedProgram(_))));
+    }
+}
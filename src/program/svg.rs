@@ -0,0 +1,94 @@
+use super::{Point, Program};
+
+use std::fmt::Write;
+
+/// The width and height of a codel in the rendered SVG
+const SVG_CODEL_SIZE: u32 = 16;
+
+impl Program {
+    /// Render the program as an SVG image with a shape for every colour block.
+    ///
+    /// Each block is a single path made up of the rows of codels it covers, so blocks of any
+    /// shape are drawn as the union of their codels. Coloured blocks are labelled with their
+    /// number of codels on their first codel in reading order.
+    pub fn to_svg(&self) -> String {
+        let width = self.cols * SVG_CODEL_SIZE;
+        let height = self.rows * SVG_CODEL_SIZE;
+
+        let mut svg = String::new();
+
+        writeln!(
+            svg,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\" shape-rendering=\"crispEdges\">",
+            width, height, width, height
+        )
+        .unwrap();
+
+        for block in &self.blocks {
+            let image::Rgb([r, g, b]) = block.color().to_rgb8();
+
+            write!(svg, "  <path fill=\"#{:02x}{:02x}{:02x}\" d=\"", r, g, b).unwrap();
+
+            for (i, &(Point(row, col), len)) in codel_runs(block.area()).iter().enumerate() {
+                if i > 0 {
+                    svg.push(' ');
+                }
+
+                write!(
+                    svg,
+                    "M{} {}h{}v{}h-{}z",
+                    col * SVG_CODEL_SIZE,
+                    row * SVG_CODEL_SIZE,
+                    len * SVG_CODEL_SIZE,
+                    SVG_CODEL_SIZE,
+                    len * SVG_CODEL_SIZE
+                )
+                .unwrap();
+            }
+
+            svg.push_str("\"/>\n");
+        }
+
+        for block in self
+            .blocks
+            .iter()
+            .filter(|block| block.color().is_colored())
+        {
+            let &Point(row, col) = block.area().iter().next().unwrap();
+            // dark colours need a light label to be readable
+            let fill = match block.color().lightness_number() {
+                Some(2) => "white",
+                _ => "black",
+            };
+
+            writeln!(
+                svg,
+                "  <text x=\"{}\" y=\"{}\" fill=\"{}\" font-family=\"monospace\" font-size=\"{}\" text-anchor=\"middle\" dominant-baseline=\"central\">{}</text>",
+                col * SVG_CODEL_SIZE + SVG_CODEL_SIZE / 2,
+                row * SVG_CODEL_SIZE + SVG_CODEL_SIZE / 2,
+                fill,
+                SVG_CODEL_SIZE / 2,
+                block.num_codels()
+            )
+            .unwrap();
+        }
+
+        svg.push_str("</svg>\n");
+
+        svg
+    }
+}
+
+/// Merge codels in reading order into horizontal runs, as their first codel and length
+fn codel_runs<'a>(codels: impl IntoIterator<Item = &'a Point>) -> Vec<(Point, u32)> {
+    let mut runs: Vec<(Point, u32)> = Vec::new();
+
+    for &point in codels {
+        match runs.last_mut() {
+            Some((Point(row, col), len)) if *row == point.0 && *col + *len == point.1 => *len += 1,
+            _ => runs.push((point, 1)),
+        }
+    }
+
+    runs
+}
@@ -0,0 +1,145 @@
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color as TermColor, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Paragraph};
+use ratatui::Frame;
+
+use crate::interpreter::{HaltReason, Interpreter, StackBackend, StepOutcome};
+use crate::program::{Color, Point};
+use crate::RietError;
+
+/// Step through a program in a terminal UI, taking a single step every time a key is pressed.
+///
+/// The program is drawn as a grid with the current codel highlighted, next to the DP, CC and
+/// the stack. Pressing `q` or escape leaves the UI, returning why the program halted if it did.
+pub fn run<S: StackBackend>(
+    interpreter: &mut Interpreter<S>,
+) -> Result<Option<HaltReason>, RietError> {
+    let mut terminal = ratatui::try_init()?;
+
+    let halted = step_on_keypress(&mut terminal, interpreter);
+
+    ratatui::try_restore()?;
+
+    halted
+}
+
+fn step_on_keypress<S: StackBackend>(
+    terminal: &mut ratatui::DefaultTerminal,
+    interpreter: &mut Interpreter<S>,
+) -> Result<Option<HaltReason>, RietError> {
+    let mut last = None;
+
+    loop {
+        terminal.draw(|frame| draw(frame, interpreter, last))?;
+
+        let key = match event::read()? {
+            Event::Key(key) if key.kind == KeyEventKind::Press => key,
+            _ => continue,
+        };
+
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => break,
+            _ if matches!(last, Some(StepOutcome::Halted(_))) => {}
+            _ => last = Some(interpreter.step()?),
+        }
+    }
+
+    Ok(match last {
+        Some(StepOutcome::Halted(reason)) => Some(reason),
+        _ => None,
+    })
+}
+
+fn draw<S: StackBackend>(
+    frame: &mut Frame,
+    interpreter: &Interpreter<S>,
+    last: Option<StepOutcome>,
+) {
+    let [grid_area, side_area] =
+        Layout::horizontal([Constraint::Fill(1), Constraint::Length(32)]).areas(frame.area());
+    let [state_area, stack_area] =
+        Layout::vertical([Constraint::Length(6), Constraint::Fill(1)]).areas(side_area);
+
+    let program = interpreter.program();
+    let current = interpreter.current_codel();
+
+    // each codel is two cells wide so the grid looks roughly square
+    let grid: Vec<Line> = (0..*program.rows())
+        .map(|row| {
+            (0..*program.cols())
+                .map(|col| {
+                    let color = *program.get_codel(row, col).unwrap();
+                    let style = Style::new().bg(term_color(color));
+
+                    if Point(row, col) == current {
+                        let style = style.fg(highlight(color)).add_modifier(Modifier::BOLD);
+
+                        Span::styled("<>", style)
+                    } else {
+                        Span::styled("  ", style)
+                    }
+                })
+                .collect::<Vec<_>>()
+                .into()
+        })
+        .collect();
+
+    frame.render_widget(
+        Paragraph::new(grid).block(Block::bordered().title(" program ")),
+        grid_area,
+    );
+
+    let status = match last {
+        None => "not started".to_string(),
+        Some(StepOutcome::Moved { executed: Some(op) }) => format!("executed {}", op),
+        Some(StepOutcome::Moved { executed: None }) => "moved".to_string(),
+        Some(StepOutcome::Halted(reason)) => format!("halted: {}", reason),
+    };
+
+    let color = interpreter
+        .current_color()
+        .map_or("outside the program", Color::name);
+    let (dp, cc) = (interpreter.dp(), interpreter.cc());
+
+    let state = vec![
+        Line::from(format!("step   {}", interpreter.summary().steps)),
+        Line::from(format!("codel  {:?} {}", current, color)),
+        Line::from(format!("dp|cc  {:?}|{:?}", dp, cc)),
+        Line::from(status),
+    ];
+
+    frame.render_widget(
+        Paragraph::new(state).block(Block::bordered().title(" state, q to quit ")),
+        state_area,
+    );
+
+    // the top of the stack first, as only as many values as fit are shown
+    let stack: Vec<Line> = interpreter
+        .stack()
+        .values()
+        .iter()
+        .rev()
+        .map(|v| Line::from(v.to_string()))
+        .collect();
+
+    frame.render_widget(
+        Paragraph::new(stack).block(Block::bordered().title(" stack, top first ")),
+        stack_area,
+    );
+}
+
+fn term_color(color: Color) -> TermColor {
+    let image::Rgb([r, g, b]) = color.to_rgb8();
+
+    TermColor::Rgb(r, g, b)
+}
+
+/// A colour which stands out against `color`
+fn highlight(color: Color) -> TermColor {
+    match color.lightness_number() {
+        Some(2) | None if !color.is_white() => TermColor::White,
+        _ => TermColor::Black,
+    }
+}
@@ -0,0 +1,43 @@
+use riet::{run_and_collect, HaltReason, Program};
+
+/// Run the program in `ascii` for `steps` steps with `input`, returning its output
+fn run(ascii: &str, input: &str, steps: usize) -> String {
+    let program = Program::from_ascii(ascii).unwrap();
+    let (output, reason) = run_and_collect(program, input.as_bytes(), steps).unwrap();
+    assert_eq!(reason, HaltReason::MaxSteps);
+
+    output
+}
+
+/// Read a number with in(number) and write it back with out(number)
+fn echo_number(input: &str) -> String {
+    run("RbC\n", input, 2)
+}
+
+#[test]
+fn negative_numbers_round_trip_through_in_and_out_number() {
+    // push 1, push 3, subtract, out(number)
+    let output = run("R111rY1\n", "", 4);
+    assert_eq!(output, "-2");
+
+    assert_eq!(echo_number(&output), output);
+}
+
+#[test]
+fn numbers_too_big_for_a_machine_word_round_trip() {
+    for n in ["-12345678901234567890123", "98765432109876543210"] {
+        assert_eq!(echo_number(n), n);
+    }
+}
+
+#[test]
+fn signs_are_read_leniently() {
+    assert_eq!(echo_number("  -5 "), "-5");
+    assert_eq!(echo_number("\n-5\n"), "-5");
+    assert_eq!(echo_number("+5"), "5");
+    assert_eq!(echo_number("-0"), "0");
+
+    // a sign on its own, or apart from its digits, isn't a number
+    assert_eq!(echo_number("-"), "");
+    assert_eq!(echo_number("- 5"), "");
+}